@@ -0,0 +1,435 @@
+/*
+Summary:
+    The arena_tree_map module contains an arena-backed variant of the TreeMap
+    Every node is stored in a single growable Vec and nodes refer to each other by u32 indices
+    Each node links to its first child and its next sibling, so no node owns an allocation of its own
+    This trades the per-node Vec of the TreeMap for a single allocation that grows by doubling
+
+    Nodes are never freed individually, so the arena does not support removal
+
+Tests:
+    - Insert and find
+    - With capacity
+    - Iter
+*/
+use std::fmt::Debug;
+
+
+// Marks the end of a sibling list or a node with no children
+const NONE: u32 = u32::MAX;
+
+
+// ArenaNode<T, Idx>
+// T - The value to store
+// Idx - The key at this node's depth
+//
+// A node within the arena
+// Links to other nodes are indices into the arena rather than owned pointers
+struct ArenaNode<T, Idx> {
+    // The key for this node at its depth
+    key: Idx,
+
+    // The value stored by the node
+    // Not all nodes store values as some are intermediaries
+    value: Option<T>,
+
+    // The index of the first child, or NONE if the node is a leaf
+    first_child: u32,
+
+    // The index of the next node with the same parent, or NONE if this is the last
+    next_sibling: u32
+}
+
+
+// ArenaTreeMap<T, Idx>
+// T - The value type to store
+// Idx - The index type. Must be equatable and clonable
+//
+// A TreeMap where every node lives in one Vec
+// The root is implicit; head is the first top-level node
+pub struct ArenaTreeMap<T, Idx: PartialEq + Clone> {
+    // The arena of nodes
+    nodes: Vec<ArenaNode<T, Idx>>,
+
+    // The value of the empty index
+    // The root has no key, so its value is kept outside of the arena
+    root: Option<T>,
+
+    // The index of the first top-level node, or NONE if the tree is empty
+    head: u32,
+
+    // The number of values stored in the tree
+    len: usize
+}
+
+
+// Iter<'a, T, Idx>
+// Iterates over the (index, value) pairs of an ArenaTreeMap in depth-first order
+pub struct Iter<'a, T, Idx: PartialEq + Clone> {
+    nodes: &'a [ArenaNode<T, Idx>],
+
+    // The value of the empty index, which is yielded before any node
+    root: Option<&'a T>,
+
+    // The node indices still to visit, each with the depth of the node
+    stack: Vec<(u32, usize)>,
+
+    // The keys that lead to the node most recently visited
+    path: Vec<Idx>
+}
+
+
+
+
+/*
+Implementations
+*/
+
+
+impl<T, Idx: PartialEq + Clone> ArenaTreeMap<T, Idx> {
+    pub fn new() -> Self {
+        ArenaTreeMap {
+            nodes: vec!(),
+            root: None,
+            head: NONE,
+            len: 0
+        }
+    }
+
+    // Creates an empty tree with room for at least capacity nodes before reallocating
+    // Note that capacity counts nodes, not values. An index of length n can need up to n nodes
+    pub fn with_capacity(capacity: usize) -> Self {
+        ArenaTreeMap {
+            nodes: Vec::with_capacity(capacity),
+            root: None,
+            head: NONE,
+            len: 0
+        }
+    }
+
+    // Inserts a value into the tree
+    // This takes a slice of keys that make up the whole index
+    // If the value already exists it is ovewritten and the old value is returned
+    // The empty index is stored in the root
+    pub fn insert(&mut self, index: &[Idx], value: T) -> Option<T> {
+        let mut parent = NONE;
+        for key in index {
+            parent = self.get_or_insert_child(parent, key);
+        }
+
+        let slot = if parent == NONE { &mut self.root } else { &mut self.nodes[parent as usize].value };
+        let old = slot.replace(value);
+        if old.is_none() { self.len += 1; }
+
+        return old;
+    }
+
+    // Finds and returns a value from the tree
+    // If the value exists then a reference to it is returned
+    // Otherwise None is returned
+    pub fn find(&self, index: &[Idx]) -> Option<&T> {
+        if index.len() == 0 { return self.root.as_ref(); }
+
+        let node = self.find_node(index)?;
+
+        return self.nodes[node as usize].value.as_ref();
+    }
+
+    // Mutable counterpart of find
+    pub fn find_mut(&mut self, index: &[Idx]) -> Option<&mut T> {
+        if index.len() == 0 { return self.root.as_mut(); }
+
+        let node = self.find_node(index)?;
+
+        return self.nodes[node as usize].value.as_mut();
+    }
+
+    // Returns the number of values stored in the tree
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    // Returns true if the tree holds no values
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    // Returns the number of nodes in the arena, including intermediaries without values
+    pub fn node_count(&self) -> usize {
+        return self.nodes.len();
+    }
+
+    // Returns the number of nodes the arena can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        return self.nodes.capacity();
+    }
+
+    // Reserves room for at least additional more nodes
+    pub fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+    }
+
+    // Releases any spare capacity held by the arena
+    // Useful once a large tree has finished loading
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+    }
+
+    // Removes every value from the tree
+    // The arena keeps its capacity
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = None;
+        self.head = NONE;
+        self.len = 0;
+    }
+
+    // Returns a depth-first iterator over every (index, value) pair
+    // The value of the empty index comes first
+    pub fn iter(&self) -> Iter<'_, T, Idx> {
+        let mut stack = vec!();
+        if self.head != NONE {
+            stack.push((self.head, 0));
+        }
+
+        return Iter {
+            nodes: &self.nodes,
+            root: self.root.as_ref(),
+            stack,
+            path: vec!()
+        };
+    }
+
+
+    // Returns the first child of parent, where NONE is the implicit root
+    fn first_child(&self, parent: u32) -> u32 {
+        if parent == NONE { return self.head; }
+
+        return self.nodes[parent as usize].first_child;
+    }
+
+    // Walks the tree to the node at the given index
+    // The empty index has no node, as the root is implicit
+    fn find_node(&self, index: &[Idx]) -> Option<u32> {
+        if index.len() == 0 { return None; }
+
+        let mut parent = NONE;
+        for key in index {
+            let mut current = self.first_child(parent);
+            while current != NONE && self.nodes[current as usize].key != *key {
+                current = self.nodes[current as usize].next_sibling;
+            }
+
+            if current == NONE { return None; }
+            parent = current;
+        }
+
+        return Some(parent);
+    }
+
+    // Returns the child of parent with the given key, appending a new one if it doesn't exist
+    fn get_or_insert_child(&mut self, parent: u32, key: &Idx) -> u32 {
+        let mut last = NONE;
+        let mut current = self.first_child(parent);
+        while current != NONE {
+            if self.nodes[current as usize].key == *key {
+                return current;
+            }
+
+            last = current;
+            current = self.nodes[current as usize].next_sibling;
+        }
+
+        // NONE is reserved, so the arena can hold at most u32::MAX nodes
+        let new = u32::try_from(self.nodes.len())
+            .ok()
+            .filter(|i| *i != NONE)
+            .expect("ArenaTreeMap cannot hold more than u32::MAX nodes");
+
+        self.nodes.push(ArenaNode {
+            key: key.clone(),
+            value: None,
+            first_child: NONE,
+            next_sibling: NONE
+        });
+
+        // Link the new node onto the end of the sibling list
+        if last != NONE {
+            self.nodes[last as usize].next_sibling = new;
+        } else if parent != NONE {
+            self.nodes[parent as usize].first_child = new;
+        } else {
+            self.head = new;
+        }
+
+        return new;
+    }
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone> Iterator for Iter<'a, T, Idx> {
+    type Item = (Vec<Idx>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(value) = self.root.take() {
+            return Some((vec!(), value));
+        }
+
+        while let Some((index, depth)) = self.stack.pop() {
+            let node = &self.nodes[index as usize];
+
+            // Siblings are visited after this node's children
+            if node.next_sibling != NONE {
+                self.stack.push((node.next_sibling, depth));
+            }
+            if node.first_child != NONE {
+                self.stack.push((node.first_child, depth + 1));
+            }
+
+            self.path.truncate(depth);
+            self.path.push(node.key.clone());
+
+            if let Some(value) = node.value.as_ref() {
+                return Some((self.path.clone(), value));
+            }
+        }
+
+        return None;
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> Default for ArenaTreeMap<T, Idx> {
+    fn default() -> Self {
+        ArenaTreeMap::new()
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> FromIterator<(Vec<Idx>, T)> for ArenaTreeMap<T, Idx> {
+    fn from_iter<I: IntoIterator<Item = (Vec<Idx>, T)>>(iter: I) -> Self {
+        let mut map = ArenaTreeMap::new();
+        map.extend(iter);
+
+        return map;
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> Extend<(Vec<Idx>, T)> for ArenaTreeMap<T, Idx> {
+    fn extend<I: IntoIterator<Item = (Vec<Idx>, T)>>(&mut self, iter: I) {
+        for (index, value) in iter {
+            self.insert(&index, value);
+        }
+    }
+}
+
+
+// Formats the tree as a map from full index to value
+impl<T: Debug, Idx: PartialEq + Clone + Debug> Debug for ArenaTreeMap<T, Idx> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.iter())
+            .finish()
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    Test: Insert and Find
+
+    Summary:
+        Inserts values at several depths, including the empty index, and checks they can be found, overwritten and mutated
+     */
+    #[test]
+    fn insert_find() {
+        let mut map: ArenaTreeMap<i32, &str> = ArenaTreeMap::new();
+
+        assert_eq!(map.insert(vec!["a", "b"].as_slice(), 1), None);
+        assert_eq!(map.insert(vec!["a", "c"].as_slice(), 2), None);
+        assert_eq!(map.insert(vec!["a"].as_slice(), 3), None);
+        assert_eq!(map.insert(vec!["d", "e", "f"].as_slice(), 4), None);
+        assert_eq!(map.insert(vec![].as_slice(), 5), None);
+
+        assert_eq!(map.find(vec!["a", "b"].as_slice()), Some(&1));
+        assert_eq!(map.find(vec!["a", "c"].as_slice()), Some(&2));
+        assert_eq!(map.find(vec!["a"].as_slice()), Some(&3));
+        assert_eq!(map.find(vec!["d", "e", "f"].as_slice()), Some(&4));
+        assert_eq!(map.find(vec!["d", "e"].as_slice()), None);
+        assert_eq!(map.find(vec!["a", "x"].as_slice()), None);
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.node_count(), 6);
+
+        assert_eq!(map.insert(vec!["a", "b"].as_slice(), 10), Some(1));
+        *map.find_mut(vec!["a", "c"].as_slice()).unwrap() += 10;
+        assert_eq!(map.find(vec!["a", "b"].as_slice()), Some(&10));
+        assert_eq!(map.find(vec!["a", "c"].as_slice()), Some(&12));
+        assert_eq!(map.len(), 5);
+
+        // The empty index is stored in the implicit root
+        assert_eq!(map.insert(vec![].as_slice(), 6), Some(5));
+        *map.find_mut(vec![].as_slice()).unwrap() += 10;
+        assert_eq!(map.find(vec![].as_slice()), Some(&16));
+        assert_eq!(map.iter().next(), Some((vec!(), &16)));
+        assert_eq!(map.node_count(), 6);
+
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.find(vec!["a"].as_slice()), None);
+        assert_eq!(map.find(vec![].as_slice()), None);
+    }
+
+    /*
+    Test: With Capacity
+
+    Summary:
+        Checks that a presized arena does not reallocate while it is filled
+     */
+    #[test]
+    fn with_capacity() {
+        let mut map: ArenaTreeMap<usize, usize> = ArenaTreeMap::with_capacity(110);
+        let capacity = map.capacity();
+        assert!(capacity >= 110);
+
+        for i in 0..10 {
+            for j in 0..10 {
+                map.insert(vec![i, j].as_slice(), i * 10 + j);
+            }
+        }
+
+        assert_eq!(map.node_count(), 110);
+        assert_eq!(map.capacity(), capacity);
+        assert_eq!(map.find(vec![7, 3].as_slice()), Some(&73));
+    }
+
+    /*
+    Test: Iter
+
+    Summary:
+        Checks that iteration yields every value depth-first in insertion order
+     */
+    #[test]
+    fn iter() {
+        let map: ArenaTreeMap<i32, &str> = vec!(
+            (vec!["a", "b"], 1),
+            (vec!["c"], 2),
+            (vec!["a", "d"], 3),
+            (vec!["a"], 4)
+        ).into_iter().collect();
+
+        let entries: Vec<(Vec<&str>, &i32)> = map.iter().collect();
+        assert_eq!(entries, vec!(
+            (vec!["a"], &4),
+            (vec!["a", "b"], &1),
+            (vec!["a", "d"], &3),
+            (vec!["c"], &2)
+        ));
+        assert_eq!(format!("{:?}", map), r#"{["a"]: 4, ["a", "b"]: 1, ["a", "d"]: 3, ["c"]: 2}"#);
+    }
+}
@@ -0,0 +1,570 @@
+/*
+Summary:
+    The binary module saves a compiled StaticTree to a compact binary file and loads it back
+    without running StaticTreePlanner::compile again
+
+    Only plain-data keys and values can be saved. These are marked with the StaticPod trait
+    Values are written with the byte order of the machine that wrote them, and the file records
+    that byte order so that a machine with a different one refuses to load it
+
+    File layout, with every integer in the writer's byte order
+        magic       [u8; 4]     "RSST"
+        endianness  u32         0x01020304
+        version     u32         FORMAT_VERSION
+        key_size    u32         size_of::<Idx>()
+        value_size  u32         size_of::<T>()
+        node_size   u32         The size of one node record
+        node_count  u64         The number of node records, including the root
+    followed by node_count node records in pool order
+        key         u8 flag followed by size_of::<Idx>() bytes, zeroed if the flag is 0
+        value       u8 flag followed by size_of::<T>() bytes, zeroed if the flag is 0
+        list_length u32         The number of branches
+        list_head   u32         The record index of the first branch, or 0 if there are none
+    followed by the trailer
+        checksum    u64         Hash of every node record, see hash
+
+    The checksum comes after the records so the writer can hash them as they are written, in a single pass
+    Branches are stored as record indices, so files don't depend on the in-memory node layout
+    Every offset is validated when loading. Branches must come after their parent and every node except the root
+    must belong to exactly one branch list, so a loaded tree is always a proper tree
+    The records are read in bulk and validated before any node is built, then decoded into the pool in one pass
+    Memory grows with the records actually read rather than the node count in the header, so a truncated
+    or hostile header can't make the loader allocate much more than the file holds
+
+Tests:
+    - Round trip
+    - Invalid header
+    - Invalid nodes
+*/
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+use crate::static_tree::{StaticTree, TreeNode};
+
+
+// The first bytes of every file
+const MAGIC: [u8; 4] = *b"RSST";
+
+// Written in the writer's byte order, so it reads back differently on a machine with another byte order
+const ENDIANNESS: u32 = 0x0102_0304;
+
+// The version of the file layout
+// This must be incremented whenever the layout changes
+pub const FORMAT_VERSION: u32 = 2;
+
+// The size of the header in bytes
+const HEADER_SIZE: usize = 32;
+
+// Node records are written in chunks of this many records, and read in chunks of at least this many
+// This must be a multiple of 8, so every chunk but the last hashes whole words
+const CHUNK_NODES: usize = 4096;
+const _: () = assert!(CHUNK_NODES % 8 == 0);
+
+// Parameters for the checksum
+const HASH_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+const HASH_MULTIPLIER: u64 = 0xff51_afd7_ed55_8ccd;
+
+
+// StaticPod
+//
+// Marks plain-data types that can be saved in a binary StaticTree file by copying their bytes
+//
+// SAFETY: Implementors must contain no pointers or references, no padding bytes,
+// and every bit pattern of size_of::<Self>() bytes must be a valid value of the type
+// bool and char are not StaticPod as some bit patterns are invalid
+#[allow(clippy::missing_safety_doc)] // The safety contract is the comment above
+pub unsafe trait StaticPod: Copy { }
+
+// Implements StaticPod for types that meet its requirements
+macro_rules! static_pod {
+    ($($t:ty),*) => {
+        $(
+            // SAFETY: Primitive numbers have no padding and every bit pattern is valid
+            unsafe impl StaticPod for $t { }
+        )*
+    };
+}
+static_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+// SAFETY: Arrays have no padding between elements, so an array of StaticPod is StaticPod
+unsafe impl<P: StaticPod, const N: usize> StaticPod for [P; N] { }
+
+
+// Error
+//
+// The reasons a binary StaticTree file can fail to load
+#[derive(Debug)]
+pub enum Error {
+    // The reader failed or ended early
+    Io(io::Error),
+
+    // The file doesn't start with the StaticTree magic bytes
+    Magic,
+
+    // The file was written by an unsupported version of the format
+    Version(u32),
+
+    // The file was written by a machine with a different byte order
+    Endianness,
+
+    // The key, value or node sizes don't match the types being loaded
+    NodeSize,
+
+    // The node count is too large to fit in a StaticTree
+    // Nodes are addressed by u32 indices, so a tree holds at most u32::MAX nodes, counting the root and intermediaries
+    TooLarge,
+
+    // The node records don't match the checksum
+    Checksum,
+
+    // The node at this index has an invalid flag or branch offset
+    InvalidNode(u64)
+}
+
+
+
+
+/*
+Implementations
+*/
+
+
+impl<T: StaticPod, Idx: StaticPod + PartialEq> StaticTree<T, Idx> {
+    // Writes the tree to a binary file
+    // The writer isn't buffered here, but nodes are written in large chunks
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let count = self.node_count();
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&ENDIANNESS.to_ne_bytes());
+        header.extend_from_slice(&FORMAT_VERSION.to_ne_bytes());
+        header.extend_from_slice(&(std::mem::size_of::<Idx>() as u32).to_ne_bytes());
+        header.extend_from_slice(&(std::mem::size_of::<T>() as u32).to_ne_bytes());
+        header.extend_from_slice(&(node_size::<T, Idx>() as u32).to_ne_bytes());
+        header.extend_from_slice(&(count as u64).to_ne_bytes());
+        writer.write_all(&header)?;
+
+        // Each chunk is hashed as it is written, and the checksum follows the records
+        let mut checksum = HASH_SEED;
+        let mut buffer = vec!();
+        for start in (0..count).step_by(CHUNK_NODES) {
+            self.encode_chunk(start, count.min(start + CHUNK_NODES), &mut buffer);
+            checksum = hash(checksum, &buffer);
+            writer.write_all(&buffer)?;
+        }
+
+        return writer.write_all(&checksum.to_ne_bytes());
+    }
+
+    // Loads a tree written by write_to
+    // T and Idx must have the same sizes as the types the file was written with
+    // Exactly the bytes of the file are read, so a tree can be followed by other data in the same stream
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, Error> {
+        let mut header = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+
+        // Each field is read in order from the header
+        let mut fields = header[4..].chunks_exact(4);
+        let mut next_u32 = || u32::from_ne_bytes(fields.next().unwrap().try_into().unwrap());
+
+        if header[0..4] != MAGIC { return Err(Error::Magic); }
+        if next_u32() != ENDIANNESS { return Err(Error::Endianness); }
+
+        let version = next_u32();
+        if version != FORMAT_VERSION { return Err(Error::Version(version)); }
+
+        let node_size = node_size::<T, Idx>();
+        let sizes = [next_u32(), next_u32(), next_u32()];
+        if sizes != [std::mem::size_of::<Idx>() as u32, std::mem::size_of::<T>() as u32, node_size as u32] {
+            return Err(Error::NodeSize);
+        }
+
+        // Nodes are addressed by u32 indices, which limits the size of the tree
+        let count = u64::from_ne_bytes(header[24..32].try_into().unwrap());
+        let count = usize::try_from(count).ok()
+            .filter(|count| StaticTree::<T, Idx>::pool_size(*count).is_some())
+            .and_then(|count| Some((count, count.checked_mul(node_size)?)));
+        let (count, size) = count.ok_or(Error::TooLarge)?;
+        if count == 0 { return Err(Error::InvalidNode(0)); }
+
+        // The node count can't be trusted until the records have arrived, so the buffer isn't sized from it
+        // Each read is at most as large as everything read so far, so the buffer is never more than twice the records read
+        let mut records = vec!();
+        while records.len() < size {
+            let start = records.len();
+            records.resize(size.min(start + start.max(CHUNK_NODES * node_size)), 0);
+            reader.read_exact(&mut records[start..])?;
+        }
+
+        let mut checksum = [0u8; 8];
+        reader.read_exact(&mut checksum)?;
+        if hash(HASH_SEED, &records) != u64::from_ne_bytes(checksum) { return Err(Error::Checksum); }
+
+        // The branch list of every node that has branches, as a range of record indices
+        let mut lists = vec!();
+        for (index, record) in records.chunks_exact(node_size).enumerate() {
+            match validate_record::<T, Idx>(record, index, count) {
+                Some(list) => { lists.extend(list); },
+                None => { return Err(Error::InvalidNode(index as u64)); }
+            };
+        }
+
+        // Every node except the root must belong to exactly one branch list, so once sorted
+        // the lists must cover every index after the root without gaps or overlaps
+        lists.sort_unstable_by_key(|list: &Range<usize>| list.start);
+        let mut next = 1;
+        for list in lists {
+            // A gap leaves next unreachable, and an overlap claims list.start twice
+            if list.start != next { return Err(Error::InvalidNode(next.min(list.start) as u64)); }
+            next = list.end;
+        }
+        if next != count { return Err(Error::InvalidNode(next as u64)); }
+
+        // Every record is valid, so the nodes are decoded straight into a pool of exactly count nodes
+        let nodes = records.chunks_exact(node_size).map(decode_node).collect();
+
+        return Ok(StaticTree::from_nodes(nodes));
+    }
+
+
+
+    /* Helper Methods */
+    // Encodes the node records from start up to end into the buffer, replacing its contents
+    fn encode_chunk(&self, start: usize, end: usize, buffer: &mut Vec<u8>) {
+        buffer.clear();
+
+        for index in start..end {
+            let node = self.node(index);
+
+            encode_option(node.key.as_ref(), buffer);
+            encode_option(node.value.as_ref(), buffer);
+
+            // A node with no branches may still have a list_head, such as a root without children
+            let list_head = if node.list_length == 0 { 0 } else { node.list_head };
+            buffer.extend_from_slice(&node.list_length.to_ne_bytes());
+            buffer.extend_from_slice(&list_head.to_ne_bytes());
+        }
+    }
+}
+
+
+// Returns the size of one node record in a file
+fn node_size<T, Idx>() -> usize {
+    return 1 + std::mem::size_of::<Idx>() + 1 + std::mem::size_of::<T>() + 4 + 4;
+}
+
+// Folds bytes into the checksum 8 bytes at a time
+// A trailing partial word is padded with zeroes, so a stream must be hashed in pieces that are multiples of 8 bytes
+fn hash(mut hash: u64, bytes: &[u8]) -> u64 {
+    let mut words = bytes.chunks_exact(8);
+    for word in &mut words {
+        hash = mix(hash, u64::from_le_bytes(word.try_into().unwrap()));
+    }
+
+    let rest = words.remainder();
+    if rest.len() > 0 {
+        let mut word = [0u8; 8];
+        word[..rest.len()].copy_from_slice(rest);
+        hash = mix(hash, u64::from_le_bytes(word));
+    }
+
+    return hash;
+}
+
+// Folds one word into the checksum
+#[inline]
+fn mix(hash: u64, word: u64) -> u64 {
+    return (hash ^ word).wrapping_mul(HASH_MULTIPLIER).rotate_left(31);
+}
+
+// Appends a flag byte and the bytes of the value, or zeroes if there is no value
+fn encode_option<P: StaticPod>(value: Option<&P>, buffer: &mut Vec<u8>) {
+    match value {
+        Some(value) => {
+            buffer.push(1);
+
+            // SAFETY: StaticPod types have no padding, so every byte of the value is initialised
+            let bytes = unsafe { std::slice::from_raw_parts((value as *const P).cast::<u8>(), std::mem::size_of::<P>()) };
+            buffer.extend_from_slice(bytes);
+        },
+        None => {
+            buffer.push(0);
+            buffer.resize(buffer.len() + std::mem::size_of::<P>(), 0);
+        }
+    };
+}
+
+// Splits a flag byte and a value from the front of the record, advancing past them
+// Returns the flag and the bytes of the value
+fn split_option<'a, P: StaticPod>(record: &mut &'a [u8]) -> (u8, &'a [u8]) {
+    let (field, rest) = record.split_at(1 + std::mem::size_of::<P>());
+    *record = rest;
+
+    return (field[0], &field[1..]);
+}
+
+// Reads a value written by encode_option from the front of the record, advancing past it
+// The flag must already have been validated
+fn decode_option<P: StaticPod>(record: &mut &[u8]) -> Option<P> {
+    return match split_option::<P>(record) {
+        (0, _) => None,
+        // SAFETY: The field holds size_of::<P>() bytes and every bit pattern is a valid StaticPod
+        (_, bytes) => Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast::<P>()) })
+    };
+}
+
+// Splits the branch count and the first branch from the end of a record
+fn split_list(record: &[u8]) -> (u32, u32) {
+    let list_length = u32::from_ne_bytes(record[0..4].try_into().unwrap());
+    let list_head = u32::from_ne_bytes(record[4..8].try_into().unwrap());
+
+    return (list_length, list_head);
+}
+
+// Validates the node record at index in a file of count records
+// Returns the record indices of its branches if it has any, or None if the record is invalid
+fn validate_record<T: StaticPod, Idx: StaticPod>(mut record: &[u8], index: usize, count: usize) -> Option<Option<Range<usize>>> {
+    let (key_flag, _) = split_option::<Idx>(&mut record);
+    let (value_flag, _) = split_option::<T>(&mut record);
+    let (list_length, list_head) = split_list(record);
+
+    if key_flag > 1 || value_flag > 1 { return None; }
+
+    // Only the root has no key
+    if (key_flag == 0) != (index == 0) { return None; }
+
+    return match (list_length, list_head) {
+        (0, 0) => Some(None),
+        (0, _) => None,
+        _ => {
+            // Branches come after their parent, so the tree can't contain a cycle
+            // The end is checked for overflow, as both fields come from the file and usize may be 32 bits
+            let start = list_head as usize;
+            let end = start.checked_add(list_length as usize)?;
+            if start <= index || end <= start || end > count { return None; }
+
+            Some(Some(start..end))
+        }
+    };
+}
+
+// Decodes a node record that has been validated
+// The record indices of its branches are already the indices of the nodes in the pool
+fn decode_node<T: StaticPod, Idx: StaticPod + PartialEq>(mut record: &[u8]) -> TreeNode<T, Idx> {
+    let key = decode_option::<Idx>(&mut record);
+    let value = decode_option::<T>(&mut record);
+    let (list_length, list_head) = split_list(record);
+
+    return TreeNode { key, value, list_length, list_head };
+}
+
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "failed to read StaticTree: {}", error),
+            Error::Magic => write!(f, "not a StaticTree file"),
+            Error::Version(version) => write!(f, "unsupported StaticTree file version {}, expected {}", version, FORMAT_VERSION),
+            Error::Endianness => write!(f, "StaticTree file was written with a different byte order"),
+            Error::NodeSize => write!(f, "StaticTree file was written with different key or value types"),
+            Error::TooLarge => write!(f, "StaticTree file has more nodes than fit in a StaticTree"),
+            Error::Checksum => write!(f, "StaticTree file is corrupt"),
+            Error::InvalidNode(index) => write!(f, "StaticTree file has an invalid node at index {}", index)
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::PatternSegment;
+    use crate::static_tree::MAX_NODES;
+    use crate::StaticTreePlanner;
+
+    // Builds a small tree with a root value, intermediaries and leaves
+    fn tree() -> StaticTree<u64, [u8; 2]> {
+        return StaticTreePlanner::new()
+            .add(&[], 0)
+            .add(&[*b"ab", *b"cd"], 1)
+            .add(&[*b"ab", *b"ef"], 2)
+            .add(&[*b"gh"], 3)
+            .add(&[*b"ab", *b"cd", *b"ij"], 4)
+            .compile();
+    }
+
+    // Writes the tree to bytes
+    fn bytes<T: StaticPod, Idx: StaticPod + PartialEq>(tree: &StaticTree<T, Idx>) -> Vec<u8> {
+        let mut out = vec!();
+        tree.write_to(&mut out).unwrap();
+
+        return out;
+    }
+
+    // Recomputes the checksum after the node records have been edited
+    fn reseal(file: &mut [u8]) {
+        let trailer = file.len() - 8;
+        let checksum = hash(HASH_SEED, &file[HEADER_SIZE..trailer]);
+        file[trailer..].copy_from_slice(&checksum.to_ne_bytes());
+    }
+
+    /*
+    Test: Round Trip
+
+    Summary:
+        Checks that a loaded tree holds the same values as the tree that was written
+     */
+    #[test]
+    fn round_trip() {
+        let tree = tree();
+        let file = bytes(&tree);
+        assert_eq!(file.len(), HEADER_SIZE + tree.node_count() * node_size::<u64, [u8; 2]>() + 8);
+
+        let loaded: StaticTree<u64, [u8; 2]> = StaticTree::read_from(&mut file.as_slice()).unwrap();
+        assert_eq!(loaded.node_count(), tree.node_count());
+        assert_eq!(loaded.find(&[]), Some(&0));
+        assert_eq!(loaded.find(&[*b"ab", *b"cd", *b"ij"]), Some(&4));
+        assert_eq!(loaded.find(&[*b"ab"]), None);
+
+        let mut expected = tree.find_pattern(&[PatternSegment::Multi]);
+        let mut found = loaded.find_pattern(&[PatternSegment::Multi]);
+        expected.sort();
+        found.sort();
+        assert_eq!(found, expected);
+
+        // Writing the loaded tree gives the same file
+        assert_eq!(bytes(&loaded), file);
+
+        // A tree with only a root
+        let empty: StaticTree<u8, u8> = StaticTreePlanner::new().compile();
+        let loaded: StaticTree<u8, u8> = StaticTree::read_from(&mut bytes(&empty).as_slice()).unwrap();
+        assert_eq!(loaded.find(&[]), None);
+    }
+
+    /*
+    Test: Invalid Header
+
+    Summary:
+        Checks that files with a bad header or checksum, or for other types, are rejected
+     */
+    #[test]
+    fn invalid_header() {
+        let file = bytes(&tree());
+        let read = |file: &[u8]| StaticTree::<u64, [u8; 2]>::read_from(&mut &file[..]).err().unwrap();
+
+        assert!(matches!(read(&file[..20]), Error::Io(_)));
+        assert!(matches!(read(&file[..file.len() - 1]), Error::Io(_)));
+
+        let mut bad = file.clone();
+        bad[0] = b'X';
+        assert!(matches!(read(&bad), Error::Magic));
+
+        let mut bad = file.clone();
+        bad[4..8].reverse();
+        assert!(matches!(read(&bad), Error::Endianness));
+
+        let mut bad = file.clone();
+        bad[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_ne_bytes());
+        assert!(matches!(read(&bad), Error::Version(version) if version == FORMAT_VERSION + 1));
+
+        let mut bad = file.clone();
+        bad[24..32].copy_from_slice(&u64::MAX.to_ne_bytes());
+        assert!(matches!(read(&bad), Error::TooLarge));
+
+        // A header that claims the largest tree that fits is only trusted as far as its records go
+        let mut bad = file[..HEADER_SIZE].to_vec();
+        bad[24..32].copy_from_slice(&(MAX_NODES as u64).to_ne_bytes());
+        assert!(matches!(read(&bad), Error::Io(_)));
+        bad[24..32].copy_from_slice(&(MAX_NODES as u64 + 1).to_ne_bytes());
+        assert!(matches!(read(&bad), Error::TooLarge));
+
+        let mut bad = file.clone();
+        bad[HEADER_SIZE + 5] ^= 1;
+        assert!(matches!(read(&bad), Error::Checksum));
+
+        let mut bad = file.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert!(matches!(read(&bad), Error::Checksum));
+
+        let wrong_types = StaticTree::<u32, [u8; 2]>::read_from(&mut file.as_slice());
+        assert!(matches!(wrong_types, Err(Error::NodeSize)));
+    }
+
+    /*
+    Test: Invalid Nodes
+
+    Summary:
+        Checks that node records with bad flags or branch offsets are rejected even with a valid checksum
+     */
+    #[test]
+    fn invalid_nodes() {
+        let file = bytes(&tree());
+        let node_size = node_size::<u64, [u8; 2]>();
+        let read = |file: &[u8]| StaticTree::<u64, [u8; 2]>::read_from(&mut &file[..]).err().unwrap();
+
+        // Offsets of the fields of the record at index
+        let key_flag = |index: usize| HEADER_SIZE + index * node_size;
+        let list_length = |index: usize| key_flag(index) + node_size - 8;
+        let list_head = |index: usize| key_flag(index) + node_size - 4;
+
+        // Edits a field of the file and recomputes the checksum
+        let edit = |offset: usize, bytes: &[u8]| {
+            let mut bad = file.clone();
+            bad[offset..offset + bytes.len()].copy_from_slice(bytes);
+            reseal(&mut bad);
+            bad
+        };
+
+        // The root has a key
+        assert!(matches!(read(&edit(key_flag(0), &[1])), Error::InvalidNode(0)));
+
+        // A flag that is neither 0 nor 1
+        assert!(matches!(read(&edit(key_flag(1), &[2])), Error::InvalidNode(1)));
+
+        // The root's branches point at the root
+        assert!(matches!(read(&edit(list_head(0), &0u32.to_ne_bytes())), Error::InvalidNode(0)));
+
+        // The root's branches run past the end of the file
+        assert!(matches!(read(&edit(list_length(0), &100u32.to_ne_bytes())), Error::InvalidNode(0)));
+        assert!(matches!(read(&edit(list_length(0), &u32::MAX.to_ne_bytes())), Error::InvalidNode(0)));
+
+        // The end of the root's branches overflows a u32, and so a 32-bit usize
+        let (head, length) = (u32::MAX - 1, u32::MAX);
+        assert!(head as u64 + length as u64 > u32::MAX as u64);
+        let mut bad = edit(list_length(0), &length.to_ne_bytes());
+        bad[list_head(0)..list_head(0) + 4].copy_from_slice(&head.to_ne_bytes());
+        reseal(&mut bad);
+        assert!(matches!(read(&bad), Error::InvalidNode(0)));
+
+        // A node without branches has a list_head
+        // The last node in the pool is always a leaf
+        let leaf = tree().node_count() - 1;
+        assert!(matches!(read(&edit(list_head(leaf), &7u32.to_ne_bytes())), Error::InvalidNode(index) if index == leaf as u64));
+
+        // The root loses its branches, leaving them unreachable
+        let mut bad = edit(list_length(0), &0u32.to_ne_bytes());
+        bad[list_head(0)..list_head(0) + 4].copy_from_slice(&0u32.to_ne_bytes());
+        reseal(&mut bad);
+        assert!(matches!(read(&bad), Error::InvalidNode(1)));
+
+        // The root's two branches are widened to claim a node that belongs to another branch list
+        assert!(matches!(read(&edit(list_length(0), &3u32.to_ne_bytes())), Error::InvalidNode(index) if index > 0));
+    }
+}
@@ -0,0 +1,678 @@
+/*
+Summary:
+    The child_storage module defines how a TreeNode stores its sub-nodes
+    A storage strategy is chosen with a marker type that is passed as a type parameter to TreeMap
+
+    Strategies:
+        - Unsorted  An unsorted Vec that is scanned linearly. This is the default and only requires Idx: PartialEq
+        - Sorted    A Vec that is kept sorted by key and searched with a binary search. Requires Idx: Ord
+        - Hashed    A Vec of sub-nodes indexed by a hash table of positions. Requires Idx: Hash + Eq
+
+    Unsorted is the fastest for small fanouts while Sorted and Hashed avoid the O(n) scan per level on large fanouts
+*/
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Bound, RangeBounds};
+
+use hashbrown::HashTable;
+
+use crate::tree_map::TreeNode;
+
+
+// ChildStorage<T, Idx>
+// T - The value type stored in the tree
+// Idx - The index type of the tree
+//
+// Implemented by the marker types that select a storage strategy
+// The marker only needs to implement this trait for the T and Idx types that the strategy supports
+pub trait ChildStorage<T, Idx>: Sized {
+    // The container that holds the sub-nodes of a single TreeNode
+    type Children: Children<T, Idx, Self>;
+}
+
+
+// Children<T, Idx, S>
+//
+// The interface a TreeNode uses to manage its sub-nodes
+// Each sub-node is stored along with the key that leads to it
+pub trait Children<T, Idx, S: ChildStorage<T, Idx>>: Default + Lookup<Idx, T, Idx, S> {
+    // Finds the sub-node with the given key
+    fn get(&self, key: &Idx) -> Option<&TreeNode<T, Idx, S>>;
+
+    // Mutable counterpart of get
+    fn get_mut(&mut self, key: &Idx) -> Option<&mut TreeNode<T, Idx, S>>;
+
+    // Finds the sub-node with the given key, creating an empty one if it doesn't exist
+    // The key is only cloned if a new sub-node is created
+    fn get_or_insert(&mut self, key: &Idx) -> &mut TreeNode<T, Idx, S>;
+
+    // Inserts a sub-node under the given key, replacing any existing sub-node
+    // Returns a reference to the inserted sub-node
+    fn insert(&mut self, key: Idx, node: TreeNode<T, Idx, S>) -> &mut TreeNode<T, Idx, S>;
+
+    // Removes the sub-node with the given key and returns it
+    fn remove(&mut self, key: &Idx) -> Option<TreeNode<T, Idx, S>>;
+
+    // Keeps only the sub-nodes for which f returns true
+    // The order of the remaining sub-nodes is kept
+    fn retain<F: FnMut(&Idx, &mut TreeNode<T, Idx, S>) -> bool>(&mut self, f: F);
+
+    // Returns the position of the sub-node with the given key
+    // Positions follow the iteration order of the strategy
+    fn position(&self, key: &Idx) -> Option<usize>;
+
+    // Returns the (key, sub-node) pair at a position
+    fn get_index(&self, position: usize) -> Option<(&Idx, &TreeNode<T, Idx, S>)>;
+
+    // Mutable counterpart of get_index
+    fn get_index_mut(&mut self, position: usize) -> Option<(&Idx, &mut TreeNode<T, Idx, S>)>;
+
+    // Returns the number of sub-nodes
+    fn len(&self) -> usize;
+
+    // Returns true if there are no sub-nodes
+    fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    // Iterates over the (key, sub-node) pairs in the order of the strategy
+    fn iter<'a>(&'a self) -> impl DoubleEndedIterator<Item = (&'a Idx, &'a TreeNode<T, Idx, S>)> where T: 'a, Idx: 'a, S: 'a;
+
+    // Mutable counterpart of iter
+    fn iter_mut<'a>(&'a mut self) -> impl DoubleEndedIterator<Item = (&'a Idx, &'a mut TreeNode<T, Idx, S>)> where T: 'a, Idx: 'a, S: 'a;
+
+    // Consumes the container and returns the owned (key, sub-node) pairs
+    fn into_entries(self) -> impl DoubleEndedIterator<Item = (Idx, TreeNode<T, Idx, S>)>;
+}
+
+
+
+// Lookup<Q, T, Idx, S>
+// Q - A borrowed form of Idx, in the same way as the key of HashMap::get
+//
+// Finds sub-nodes by a borrowed form of the key so that lookups don't need an owned Idx
+// Each strategy requires the same traits of Q as it does of Idx. Every container can be looked up by Idx itself
+pub trait Lookup<Q: ?Sized, T, Idx, S: ChildStorage<T, Idx>> {
+    // Finds the sub-node whose key borrows as the given key
+    fn lookup(&self, key: &Q) -> Option<&TreeNode<T, Idx, S>>;
+
+    // Mutable counterpart of lookup
+    fn lookup_mut(&mut self, key: &Q) -> Option<&mut TreeNode<T, Idx, S>>;
+}
+
+
+
+/*
+Strategy Markers
+*/
+
+// Stores sub-nodes in an unsorted Vec in insertion order
+// Lookups are a linear scan
+pub struct Unsorted;
+
+// Stores sub-nodes in a Vec sorted by key
+// Lookups are a binary search and iteration comes out in key order
+pub struct Sorted;
+
+// Stores sub-nodes in a Vec with a hash table of their positions, in the same way as an IndexMap
+// Lookups are O(1) and iteration is in insertion order until a sub-node is removed
+pub struct Hashed;
+
+
+
+/*
+Containers
+*/
+
+// Container for the Unsorted strategy
+pub struct UnsortedChildren<T, Idx: PartialEq + Clone> {
+    nodes: Vec<(Idx, TreeNode<T, Idx, Unsorted>)>
+}
+
+// Container for the Sorted strategy
+pub struct SortedChildren<T, Idx: Ord + Clone> {
+    // Always sorted by key
+    nodes: Vec<(Idx, TreeNode<T, Idx, Sorted>)>
+}
+
+// Container for the Hashed strategy
+pub struct HashedChildren<T, Idx: Hash + Eq + Clone> {
+    // The position in nodes of every sub-node, hashed by its key
+    // Keys are only stored in nodes, and the table compares them through the positions it holds
+    index: HashTable<usize>,
+
+    // Hashes the keys for the index
+    hasher: RandomState,
+
+    nodes: Vec<(Idx, TreeNode<T, Idx, Hashed>)>
+}
+
+
+
+/* Unsorted Implementation */
+impl<T, Idx: PartialEq + Clone> ChildStorage<T, Idx> for Unsorted {
+    type Children = UnsortedChildren<T, Idx>;
+}
+impl<T: Clone, Idx: PartialEq + Clone> Clone for UnsortedChildren<T, Idx> {
+    fn clone(&self) -> Self {
+        UnsortedChildren { nodes: self.nodes.clone() }
+    }
+}
+impl<T, Idx: PartialEq + Clone> Default for UnsortedChildren<T, Idx> {
+    fn default() -> Self {
+        UnsortedChildren { nodes: vec!() }
+    }
+}
+impl<T, Idx: PartialEq + Clone> Children<T, Idx, Unsorted> for UnsortedChildren<T, Idx> {
+    fn get(&self, key: &Idx) -> Option<&TreeNode<T, Idx, Unsorted>> {
+        return self.nodes.iter().find(|(k, _)| k == key).map(|(_, node)| node);
+    }
+
+    fn get_mut(&mut self, key: &Idx) -> Option<&mut TreeNode<T, Idx, Unsorted>> {
+        return self.nodes.iter_mut().find(|(k, _)| k == key).map(|(_, node)| node);
+    }
+
+    fn get_or_insert(&mut self, key: &Idx) -> &mut TreeNode<T, Idx, Unsorted> {
+        let position = match self.position(key) {
+            Some(p) => p,
+            None => {
+                self.nodes.push((key.clone(), TreeNode::new()));
+                self.nodes.len() - 1
+            }
+        };
+
+        return &mut self.nodes[position].1;
+    }
+
+    fn insert(&mut self, key: Idx, node: TreeNode<T, Idx, Unsorted>) -> &mut TreeNode<T, Idx, Unsorted> {
+        let position = match self.position(&key) {
+            Some(p) => {
+                self.nodes[p].1 = node;
+                p
+            },
+            None => {
+                self.nodes.push((key, node));
+                self.nodes.len() - 1
+            }
+        };
+
+        return &mut self.nodes[position].1;
+    }
+
+    fn remove(&mut self, key: &Idx) -> Option<TreeNode<T, Idx, Unsorted>> {
+        // Vec::remove is used over swap_remove to keep the insertion order
+        let position = self.position(key)?;
+
+        return Some(self.nodes.remove(position).1);
+    }
+
+    fn retain<F: FnMut(&Idx, &mut TreeNode<T, Idx, Unsorted>) -> bool>(&mut self, mut f: F) {
+        self.nodes.retain_mut(|(key, node)| f(key, node));
+    }
+
+    fn position(&self, key: &Idx) -> Option<usize> {
+        return self.nodes.iter().position(|(k, _)| k == key);
+    }
+
+    fn get_index(&self, position: usize) -> Option<(&Idx, &TreeNode<T, Idx, Unsorted>)> {
+        return self.nodes.get(position).map(|(key, node)| (key, node));
+    }
+
+    fn get_index_mut(&mut self, position: usize) -> Option<(&Idx, &mut TreeNode<T, Idx, Unsorted>)> {
+        return self.nodes.get_mut(position).map(|(key, node)| (&*key, node));
+    }
+
+    fn len(&self) -> usize {
+        return self.nodes.len();
+    }
+
+    fn iter<'a>(&'a self) -> impl DoubleEndedIterator<Item = (&'a Idx, &'a TreeNode<T, Idx, Unsorted>)> where T: 'a, Idx: 'a {
+        return self.nodes.iter().map(|(key, node)| (key, node));
+    }
+
+    fn iter_mut<'a>(&'a mut self) -> impl DoubleEndedIterator<Item = (&'a Idx, &'a mut TreeNode<T, Idx, Unsorted>)> where T: 'a, Idx: 'a {
+        return self.nodes.iter_mut().map(|(key, node)| (&*key, node));
+    }
+
+    fn into_entries(self) -> impl DoubleEndedIterator<Item = (Idx, TreeNode<T, Idx, Unsorted>)> {
+        return self.nodes.into_iter();
+    }
+}
+
+
+
+impl<Q: ?Sized + PartialEq, T, Idx: PartialEq + Clone + Borrow<Q>> Lookup<Q, T, Idx, Unsorted> for UnsortedChildren<T, Idx> {
+    fn lookup(&self, key: &Q) -> Option<&TreeNode<T, Idx, Unsorted>> {
+        return self.nodes.iter().find(|(k, _)| k.borrow() == key).map(|(_, node)| node);
+    }
+
+    fn lookup_mut(&mut self, key: &Q) -> Option<&mut TreeNode<T, Idx, Unsorted>> {
+        return self.nodes.iter_mut().find(|(k, _)| k.borrow() == key).map(|(_, node)| node);
+    }
+}
+
+
+
+/* Sorted Implementation */
+impl<T, Idx: Ord + Clone> ChildStorage<T, Idx> for Sorted {
+    type Children = SortedChildren<T, Idx>;
+}
+impl<T: Clone, Idx: Ord + Clone> Clone for SortedChildren<T, Idx> {
+    fn clone(&self) -> Self {
+        SortedChildren { nodes: self.nodes.clone() }
+    }
+}
+impl<T, Idx: Ord + Clone> Default for SortedChildren<T, Idx> {
+    fn default() -> Self {
+        SortedChildren { nodes: vec!() }
+    }
+}
+impl<T, Idx: Ord + Clone> SortedChildren<T, Idx> {
+    // Binary searches for the key
+    // Err holds the position the key would be inserted at
+    fn search(&self, key: &Idx) -> Result<usize, usize> {
+        return self.nodes.binary_search_by(|(k, _)| k.cmp(key));
+    }
+
+    // Returns the sorted (key, sub-node) pairs whose key falls within the range
+    // Both ends of the range are found with a binary search
+    pub(crate) fn range<R: RangeBounds<Idx>>(&self, range: R) -> &[(Idx, TreeNode<T, Idx, Sorted>)] {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.nodes.partition_point(|(k, _)| k < key),
+            Bound::Excluded(key) => self.nodes.partition_point(|(k, _)| k <= key),
+            Bound::Unbounded => 0
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.nodes.partition_point(|(k, _)| k <= key),
+            Bound::Excluded(key) => self.nodes.partition_point(|(k, _)| k < key),
+            Bound::Unbounded => self.nodes.len()
+        };
+
+        // An inverted range is empty
+        if start >= end { return &[]; }
+
+        return &self.nodes[start..end];
+    }
+}
+impl<T, Idx: Ord + Clone> Children<T, Idx, Sorted> for SortedChildren<T, Idx> {
+    fn get(&self, key: &Idx) -> Option<&TreeNode<T, Idx, Sorted>> {
+        let position = self.search(key).ok()?;
+
+        return Some(&self.nodes[position].1);
+    }
+
+    fn get_mut(&mut self, key: &Idx) -> Option<&mut TreeNode<T, Idx, Sorted>> {
+        let position = self.search(key).ok()?;
+
+        return Some(&mut self.nodes[position].1);
+    }
+
+    fn get_or_insert(&mut self, key: &Idx) -> &mut TreeNode<T, Idx, Sorted> {
+        let position = match self.search(key) {
+            Ok(p) => p,
+            Err(p) => {
+                self.nodes.insert(p, (key.clone(), TreeNode::new()));
+                p
+            }
+        };
+
+        return &mut self.nodes[position].1;
+    }
+
+    fn insert(&mut self, key: Idx, node: TreeNode<T, Idx, Sorted>) -> &mut TreeNode<T, Idx, Sorted> {
+        let position = match self.search(&key) {
+            Ok(p) => {
+                self.nodes[p].1 = node;
+                p
+            },
+            Err(p) => {
+                self.nodes.insert(p, (key, node));
+                p
+            }
+        };
+
+        return &mut self.nodes[position].1;
+    }
+
+    fn remove(&mut self, key: &Idx) -> Option<TreeNode<T, Idx, Sorted>> {
+        let position = self.search(key).ok()?;
+
+        return Some(self.nodes.remove(position).1);
+    }
+
+    fn retain<F: FnMut(&Idx, &mut TreeNode<T, Idx, Sorted>) -> bool>(&mut self, mut f: F) {
+        self.nodes.retain_mut(|(key, node)| f(key, node));
+    }
+
+    fn position(&self, key: &Idx) -> Option<usize> {
+        return self.search(key).ok();
+    }
+
+    fn get_index(&self, position: usize) -> Option<(&Idx, &TreeNode<T, Idx, Sorted>)> {
+        return self.nodes.get(position).map(|(key, node)| (key, node));
+    }
+
+    fn get_index_mut(&mut self, position: usize) -> Option<(&Idx, &mut TreeNode<T, Idx, Sorted>)> {
+        return self.nodes.get_mut(position).map(|(key, node)| (&*key, node));
+    }
+
+    fn len(&self) -> usize {
+        return self.nodes.len();
+    }
+
+    fn iter<'a>(&'a self) -> impl DoubleEndedIterator<Item = (&'a Idx, &'a TreeNode<T, Idx, Sorted>)> where T: 'a, Idx: 'a {
+        return self.nodes.iter().map(|(key, node)| (key, node));
+    }
+
+    fn iter_mut<'a>(&'a mut self) -> impl DoubleEndedIterator<Item = (&'a Idx, &'a mut TreeNode<T, Idx, Sorted>)> where T: 'a, Idx: 'a {
+        return self.nodes.iter_mut().map(|(key, node)| (&*key, node));
+    }
+
+    fn into_entries(self) -> impl DoubleEndedIterator<Item = (Idx, TreeNode<T, Idx, Sorted>)> {
+        return self.nodes.into_iter();
+    }
+}
+
+
+
+impl<Q: ?Sized + Ord, T, Idx: Ord + Clone + Borrow<Q>> Lookup<Q, T, Idx, Sorted> for SortedChildren<T, Idx> {
+    fn lookup(&self, key: &Q) -> Option<&TreeNode<T, Idx, Sorted>> {
+        let position = self.nodes.binary_search_by(|(k, _)| k.borrow().cmp(key)).ok()?;
+
+        return Some(&self.nodes[position].1);
+    }
+
+    fn lookup_mut(&mut self, key: &Q) -> Option<&mut TreeNode<T, Idx, Sorted>> {
+        let position = self.nodes.binary_search_by(|(k, _)| k.borrow().cmp(key)).ok()?;
+
+        return Some(&mut self.nodes[position].1);
+    }
+}
+
+
+
+/* Hashed Implementation */
+impl<T, Idx: Hash + Eq + Clone> ChildStorage<T, Idx> for Hashed {
+    type Children = HashedChildren<T, Idx>;
+}
+impl<T: Clone, Idx: Hash + Eq + Clone> Clone for HashedChildren<T, Idx> {
+    fn clone(&self) -> Self {
+        HashedChildren {
+            index: self.index.clone(),
+            hasher: self.hasher.clone(),
+            nodes: self.nodes.clone()
+        }
+    }
+}
+impl<T, Idx: Hash + Eq + Clone> Default for HashedChildren<T, Idx> {
+    fn default() -> Self {
+        HashedChildren {
+            index: HashTable::new(),
+            hasher: RandomState::new(),
+            nodes: vec!()
+        }
+    }
+}
+impl<T, Idx: Hash + Eq + Clone> HashedChildren<T, Idx> {
+    // Returns the position of the sub-node whose key borrows as the given key
+    fn find<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> Option<usize> where Idx: Borrow<Q> {
+        let nodes = &self.nodes;
+
+        return self.index.find(self.hasher.hash_one(key), |position| nodes[*position].0.borrow() == key).copied();
+    }
+
+    // Appends a sub-node and indexes its position
+    // The key must not already be in the container
+    fn push(&mut self, key: Idx, node: TreeNode<T, Idx, Hashed>) -> usize {
+        let position = self.nodes.len();
+        let (nodes, hasher) = (&self.nodes, &self.hasher);
+
+        // The table may grow, which rehashes the positions already in it through their keys
+        self.index.insert_unique(hasher.hash_one(&key), position, |p| hasher.hash_one(&nodes[*p].0));
+        self.nodes.push((key, node));
+
+        return position;
+    }
+}
+impl<T, Idx: Hash + Eq + Clone> Children<T, Idx, Hashed> for HashedChildren<T, Idx> {
+    fn get(&self, key: &Idx) -> Option<&TreeNode<T, Idx, Hashed>> {
+        let position = self.find(key)?;
+
+        return Some(&self.nodes[position].1);
+    }
+
+    fn get_mut(&mut self, key: &Idx) -> Option<&mut TreeNode<T, Idx, Hashed>> {
+        let position = self.find(key)?;
+
+        return Some(&mut self.nodes[position].1);
+    }
+
+    fn get_or_insert(&mut self, key: &Idx) -> &mut TreeNode<T, Idx, Hashed> {
+        let position = match self.find(key) {
+            Some(p) => p,
+            None => self.push(key.clone(), TreeNode::new())
+        };
+
+        return &mut self.nodes[position].1;
+    }
+
+    fn insert(&mut self, key: Idx, node: TreeNode<T, Idx, Hashed>) -> &mut TreeNode<T, Idx, Hashed> {
+        let position = match self.find(&key) {
+            Some(p) => {
+                self.nodes[p].1 = node;
+                p
+            },
+            None => self.push(key, node)
+        };
+
+        return &mut self.nodes[position].1;
+    }
+
+    fn remove(&mut self, key: &Idx) -> Option<TreeNode<T, Idx, Hashed>> {
+        let nodes = &self.nodes;
+        let entry = self.index.find_entry(self.hasher.hash_one(key), |position| nodes[*position].0 == *key).ok()?;
+        let (position, _) = entry.remove();
+
+        // Swap the last sub-node into the gap so only one position needs updating
+        let (_, node) = self.nodes.swap_remove(position);
+        if position < self.nodes.len() {
+            let last = self.nodes.len();
+            let hash = self.hasher.hash_one(&self.nodes[position].0);
+            *self.index.find_mut(hash, |p| *p == last).unwrap() = position;
+        }
+
+        return Some(node);
+    }
+
+    fn retain<F: FnMut(&Idx, &mut TreeNode<T, Idx, Hashed>) -> bool>(&mut self, mut f: F) {
+        // Whether each sub-node from the first removed one onwards was kept
+        // Nothing is recorded until a sub-node is removed, so a retain that keeps everything doesn't allocate
+        let mut first_removed = None;
+        let mut kept = vec!();
+        let mut position = 0;
+        self.nodes.retain_mut(|(key, node)| {
+            let keep = f(key, node);
+            if !keep && first_removed.is_none() { first_removed = Some(position); }
+            if first_removed.is_some() { kept.push(keep); }
+            position += 1;
+
+            keep
+        });
+
+        let Some(first_removed) = first_removed else { return; };
+
+        // Positions before the first removed sub-node are unchanged, and later ones shift down past each removal
+        // The table is fixed up in place, so no key is hashed or cloned
+        let mut next = first_removed;
+        let moved: Vec<Option<usize>> = kept.into_iter()
+            .map(|keep| keep.then(|| { next += 1; next - 1 }))
+            .collect();
+
+        self.index.retain(|position| {
+            if *position < first_removed { return true; }
+
+            return match moved[*position - first_removed] {
+                Some(p) => { *position = p; true },
+                None => false
+            };
+        });
+    }
+
+    fn position(&self, key: &Idx) -> Option<usize> {
+        return self.find(key);
+    }
+
+    fn get_index(&self, position: usize) -> Option<(&Idx, &TreeNode<T, Idx, Hashed>)> {
+        return self.nodes.get(position).map(|(key, node)| (key, node));
+    }
+
+    fn get_index_mut(&mut self, position: usize) -> Option<(&Idx, &mut TreeNode<T, Idx, Hashed>)> {
+        return self.nodes.get_mut(position).map(|(key, node)| (&*key, node));
+    }
+
+    fn len(&self) -> usize {
+        return self.nodes.len();
+    }
+
+    fn iter<'a>(&'a self) -> impl DoubleEndedIterator<Item = (&'a Idx, &'a TreeNode<T, Idx, Hashed>)> where T: 'a, Idx: 'a {
+        return self.nodes.iter().map(|(key, node)| (key, node));
+    }
+
+    fn iter_mut<'a>(&'a mut self) -> impl DoubleEndedIterator<Item = (&'a Idx, &'a mut TreeNode<T, Idx, Hashed>)> where T: 'a, Idx: 'a {
+        return self.nodes.iter_mut().map(|(key, node)| (&*key, node));
+    }
+
+    fn into_entries(self) -> impl DoubleEndedIterator<Item = (Idx, TreeNode<T, Idx, Hashed>)> {
+        return self.nodes.into_iter();
+    }
+}
+impl<Q: ?Sized + Hash + Eq, T, Idx: Hash + Eq + Clone + Borrow<Q>> Lookup<Q, T, Idx, Hashed> for HashedChildren<T, Idx> {
+    fn lookup(&self, key: &Q) -> Option<&TreeNode<T, Idx, Hashed>> {
+        let position = self.find(key)?;
+
+        return Some(&self.nodes[position].1);
+    }
+
+    fn lookup_mut(&mut self, key: &Q) -> Option<&mut TreeNode<T, Idx, Hashed>> {
+        let position = self.find(key)?;
+
+        return Some(&mut self.nodes[position].1);
+    }
+}
+
+
+
+
+/*
+Tests:
+    - Sorted    Insert, find, remove and key ordering
+    - Hashed    Insert, find and remove, including the position fix-up on removal
+    - HashedRetain  Retain keeps the index in step with the shifted positions
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TreeMap;
+
+    /*
+    Test: Sorted
+
+    Summary:
+        Checks that a TreeMap using the Sorted strategy behaves like the default TreeMap
+        and that iteration comes out in key order
+     */
+    #[test]
+    fn sorted() {
+        let mut map: TreeMap<i32, &str, Sorted> = TreeMap::new();
+        map.insert(vec!["c", "z"].as_slice(), 1);
+        map.insert(vec!["a"].as_slice(), 2);
+        map.insert(vec!["c", "b"].as_slice(), 3);
+        map.insert(vec!["b"].as_slice(), 4);
+
+        assert_eq!(*map.find(vec!["c", "z"].as_slice()).unwrap(), 1);
+        assert_eq!(*map.find(vec!["c", "b"].as_slice()).unwrap(), 3);
+        assert!(map.find(vec!["c", "a"].as_slice()).is_none());
+
+        let keys: Vec<Vec<&str>> = map.keys().collect();
+        assert_eq!(keys, vec!(vec!["a"], vec!["b"], vec!["c", "b"], vec!["c", "z"]));
+
+        assert_eq!(map.remove(vec!["c", "b"].as_slice()), Some(3));
+        assert!(map.find(vec!["c", "b"].as_slice()).is_none());
+        assert_eq!(*map.find(vec!["c", "z"].as_slice()).unwrap(), 1);
+    }
+
+
+    /*
+    Test: Hashed
+
+    Summary:
+        Checks that a TreeMap using the Hashed strategy can find every sub-node
+        after others have been swapped into the positions of removed sub-nodes
+     */
+    #[test]
+    fn hashed() {
+        let mut map: TreeMap<usize, String, Hashed> = TreeMap::new();
+        for i in 0..100 {
+            map.insert(vec!["root".to_string(), i.to_string()].as_slice(), i);
+        }
+
+        // Remove every even key
+        for i in (0..100).step_by(2) {
+            assert_eq!(map.remove(vec!["root".to_string(), i.to_string()].as_slice()), Some(i));
+        }
+
+        for i in 0..100 {
+            let value = map.find(vec!["root".to_string(), i.to_string()].as_slice());
+
+            if i % 2 == 0 {
+                assert!(value.is_none());
+            } else {
+                assert_eq!(*value.unwrap(), i);
+            }
+        }
+    }
+
+
+    /*
+    Test: HashedRetain
+
+    Summary:
+        Checks that retain leaves the index untouched when nothing is removed,
+        and that every remaining sub-node is found at its new position when some are
+     */
+    #[test]
+    fn hashed_retain() {
+        let mut children: HashedChildren<usize, String> = HashedChildren::default();
+        for i in 0..50 {
+            children.insert(i.to_string(), TreeNode::new());
+        }
+
+        // Keep everything
+        children.retain(|_, _| true);
+        assert_eq!(children.len(), 50);
+        for i in 0..50 {
+            assert_eq!(children.position(&i.to_string()), Some(i));
+        }
+
+        // Remove every multiple of 3, along with the first sub-node
+        children.retain(|key, _| key.parse::<usize>().unwrap() % 3 != 0);
+        assert_eq!(children.len(), 33);
+
+        for i in 0..50 {
+            let key = i.to_string();
+            match children.position(&key) {
+                Some(p) => {
+                    assert!(i % 3 != 0);
+                    assert_eq!(children.get_index(p).unwrap().0, &key);
+                },
+                None => assert!(i % 3 == 0)
+            }
+        }
+
+        // The order of the remaining sub-nodes is kept
+        let keys: Vec<usize> = children.iter().map(|(key, _)| key.parse().unwrap()).collect();
+        assert_eq!(keys, (0..50).filter(|i| i % 3 != 0).collect::<Vec<usize>>());
+        assert!(children.lookup("4").is_some());
+    }
+}
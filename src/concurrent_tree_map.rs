@@ -0,0 +1,669 @@
+/*
+Summary:
+    The concurrent_tree_map module contains a variant of the TreeMap that can be shared between threads
+    Every node keeps its value and its list of sub-nodes behind separate RwLocks, so there is no global lock
+    Lookups take a read lock on one node at a time while walking the path, and writers only hold the locks
+    of the nodes they change. Writers to disjoint subtrees therefore never block each other
+
+    Sub-nodes are held by Arc so that a walk can release the parent's lock as soon as it has the child
+    Each value sits in its own Arc, and guards own a lock on it, so a guard keeps the value alive by itself
+
+    Removing a value prunes the nodes that no longer lead to a value, from the bottom up
+    A node is unlinked under its parent's write lock and marked as detached while both of its own locks are held
+    Writers check for this under the lock they write through and start their walk again if the node was pruned,
+    so a concurrent insert never lands in a node that has just been unlinked
+    A node whose value is locked by someone else is left in place, and compact prunes anything left behind
+
+Tests:
+    - Insert and find
+    - Entry
+    - Empty index
+    - Remove and compact
+    - Threads
+    - Churn
+*/
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use parking_lot::lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard};
+use parking_lot::{RawRwLock, RwLock};
+
+
+// ConcurrentNode<T, Idx>
+// T - The value to store
+// Idx - The index type. Must be equatable and clonable
+//
+// A node of a ConcurrentTreeMap
+// The value and the sub-nodes are locked independently
+struct ConcurrentNode<T, Idx> {
+    // The value stored by the node
+    // It has its own Arc so that guards can hold its lock without borrowing the node
+    value: Arc<RwLock<Option<T>>>,
+
+    // The sub-nodes underneath this node, each with its key at this depth
+    nodes: RwLock<Vec<SubNode<T, Idx>>>,
+
+    // Set once the node has been unlinked from its parent
+    // It is only set while both of the node's locks are held, so either lock is enough to read it
+    detached: AtomicBool
+}
+
+
+// A sub-node along with its key
+type SubNode<T, Idx> = (Idx, Arc<ConcurrentNode<T, Idx>>);
+
+
+// ConcurrentTreeMap<T, Idx>
+// T - The value type to store
+// Idx - The index type. Must be equatable and clonable
+//
+// A tree map where every operation goes through &self
+// The root holds no key and holds the value of the empty index
+pub struct ConcurrentTreeMap<T, Idx: PartialEq + Clone> {
+    // The top-most node of the tree
+    // The head is never pruned
+    head: Arc<ConcurrentNode<T, Idx>>,
+
+    // The number of values stored in the tree
+    len: AtomicUsize
+}
+
+
+// ReadGuard<T>
+// Shared access to a value in a ConcurrentTreeMap
+// The value cannot be changed or removed while the guard is held
+pub struct ReadGuard<T> {
+    guard: ArcRwLockReadGuard<RawRwLock, Option<T>>
+}
+
+
+// WriteGuard<T>
+// Exclusive access to a value in a ConcurrentTreeMap
+// Other readers and writers of the same value block until the guard is dropped
+pub struct WriteGuard<T> {
+    guard: ArcRwLockWriteGuard<RawRwLock, Option<T>>
+}
+
+
+
+
+/*
+Implementations
+*/
+
+
+impl<T, Idx: PartialEq + Clone> ConcurrentNode<T, Idx> {
+    fn new() -> Self {
+        ConcurrentNode {
+            value: Arc::new(RwLock::new(None)),
+            nodes: RwLock::new(vec!()),
+            detached: AtomicBool::new(false)
+        }
+    }
+
+    // Returns true once the node has been unlinked from its parent
+    // The caller must hold one of the node's locks for the answer to stay true
+    fn is_detached(&self) -> bool {
+        return self.detached.load(Ordering::Acquire);
+    }
+
+    // Returns the sub-node with the given key
+    // Only the read lock of this node is taken, and it is released before returning
+    fn child(&self, key: &Idx) -> Option<Arc<ConcurrentNode<T, Idx>>> {
+        return self.nodes.read().iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, node)| Arc::clone(node));
+    }
+
+    // Returns the sub-node with the given key, creating it if it doesn't exist
+    // Returns None if this node has been unlinked, as a sub-node created now would be lost
+    fn child_or_insert(&self, key: &Idx) -> Option<Arc<ConcurrentNode<T, Idx>>> {
+        // Most walks find an existing node, so try with the read lock first
+        if let Some(node) = self.child(key) {
+            return Some(node);
+        }
+
+        // Another writer may have added the node between the two locks, so check again
+        let mut nodes = self.nodes.write();
+        if self.is_detached() { return None; }
+        if let Some((_, node)) = nodes.iter().find(|(k, _)| k == key) {
+            return Some(Arc::clone(node));
+        }
+
+        let node = Arc::new(ConcurrentNode::new());
+        nodes.push((key.clone(), Arc::clone(&node)));
+
+        return Some(node);
+    }
+
+    // Unlinks the sub-node if it no longer leads to a value
+    // Returns true if the sub-node was removed
+    //
+    // Locks are taken from the parent down. The value lock is only tried, as a locked value may be about to be
+    // filled by an entry, and waiting on it could deadlock with a thread that holds a guard while walking the tree
+    fn prune(&self, child: &Arc<ConcurrentNode<T, Idx>>) -> bool {
+        let mut nodes = self.nodes.write();
+        let Some(position) = nodes.iter().position(|(_, node)| Arc::ptr_eq(node, child)) else { return false; };
+
+        let Some(value) = child.value.try_write() else { return false; };
+        let child_nodes = child.nodes.write();
+        if value.is_some() || child_nodes.len() > 0 { return false; }
+
+        child.detached.store(true, Ordering::Release);
+        nodes.remove(position);
+
+        return true;
+    }
+
+    // Prunes every sub-node below this one that no longer leads to a value
+    fn compact(&self) {
+        // The sub-nodes are copied out so that no lock is held while walking below them
+        let nodes: Vec<_> = self.nodes.read().iter().map(|(_, node)| Arc::clone(node)).collect();
+
+        for node in nodes {
+            node.compact();
+            self.prune(&node);
+        }
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> ConcurrentTreeMap<T, Idx> {
+    pub fn new() -> Self {
+        ConcurrentTreeMap {
+            head: Arc::new(ConcurrentNode::new()),
+            len: AtomicUsize::new(0)
+        }
+    }
+
+    // Inserts a value into the tree
+    // This takes a slice of keys that make up the whole index
+    // If the value already exists it is ovewritten and the old value is returned
+    // The empty index is stored in the root node
+    pub fn insert(&self, index: &[Idx], value: T) -> Option<T> {
+        let old = self.lock_or_insert(index).replace(value);
+        if old.is_none() { self.len.fetch_add(1, Ordering::Relaxed); }
+
+        return old;
+    }
+
+    // Finds a value in the tree and returns a guard that gives shared access to it
+    // If no value exists at the index then None is returned
+    pub fn find(&self, index: &[Idx]) -> Option<ReadGuard<T>> {
+        let guard = self.find_node(index)?.value.read_arc();
+        if guard.is_none() { return None; }
+
+        return Some(ReadGuard { guard });
+    }
+
+    // Finds a value in the tree and returns a guard that gives exclusive access to it
+    // If no value exists at the index then None is returned
+    pub fn find_mut(&self, index: &[Idx]) -> Option<WriteGuard<T>> {
+        let guard = self.find_node(index)?.value.write_arc();
+        if guard.is_none() { return None; }
+
+        return Some(WriteGuard { guard });
+    }
+
+    // Finds a value in the tree and returns a copy of it
+    // No lock is held once this returns
+    pub fn find_cloned(&self, index: &[Idx]) -> Option<T> where T: Clone {
+        let node = self.find_node(index)?;
+        let value = node.value.read().clone();
+
+        return value;
+    }
+
+    // Gets the entry at the given index for in-place manipulation
+    // The path is created if it doesn't exist and the value is locked for writing until the entry is dropped,
+    // so checking for a value and inserting one is atomic
+    pub fn entry(&self, index: &[Idx]) -> Entry<'_, T> {
+        let guard = WriteGuard { guard: self.lock_or_insert(index) };
+
+        if guard.guard.is_some() {
+            return Entry::Occupied(OccupiedEntry { guard });
+        }
+
+        return Entry::Vacant(VacantEntry {
+            guard,
+            len: &self.len
+        });
+    }
+
+    // Removes a value from the tree
+    // If the value exists it is returned
+    // The nodes along the index that no longer lead to a value are pruned
+    pub fn remove(&self, index: &[Idx]) -> Option<T> {
+        // The nodes along the index, starting with the head
+        let mut path = vec!(Arc::clone(&self.head));
+        for key in index {
+            let next = path.last().unwrap().child(key)?;
+            path.push(next);
+        }
+
+        let old = path.last().unwrap().value.write().take();
+        if old.is_some() { self.len.fetch_sub(1, Ordering::Relaxed); }
+
+        // Prune from the bottom up, stopping at the first node that is still needed
+        while path.len() > 1 {
+            let node = path.pop().unwrap();
+            if !path.last().unwrap().prune(&node) { break; }
+        }
+
+        return old;
+    }
+
+    // Returns the number of values stored in the tree
+    // Under concurrent writes this is a snapshot that may already be out of date
+    pub fn len(&self) -> usize {
+        return self.len.load(Ordering::Relaxed);
+    }
+
+    // Returns true if the tree holds no values
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    // Removes every node that no longer leads to a value
+    // remove prunes as it goes, so this only finds nodes left by vacant entries that were never filled
+    // and nodes whose value was locked when remove reached them
+    // Nodes are pruned in the same way as remove, so other threads can keep using the map while this runs
+    pub fn compact(&self) {
+        self.head.compact();
+    }
+
+
+    // Walks the tree to the node at the given index
+    // The empty index is the head itself
+    fn find_node(&self, index: &[Idx]) -> Option<Arc<ConcurrentNode<T, Idx>>> {
+        let mut node = Arc::clone(&self.head);
+        for key in index {
+            node = node.child(key)?;
+        }
+
+        return Some(node);
+    }
+
+    // Walks the tree to the node at the given index, creating any missing nodes, and locks its value for writing
+    // If a node along the index is pruned before its value is locked the walk starts again,
+    // so the value is never written into a node that has been unlinked
+    fn lock_or_insert(&self, index: &[Idx]) -> ArcRwLockWriteGuard<RawRwLock, Option<T>> {
+        loop {
+            if let Some(node) = self.node_or_insert(index) {
+                let guard = node.value.write_arc();
+                if !node.is_detached() { return guard; }
+            }
+        }
+    }
+
+    // Walks the tree to the node at the given index, creating any missing nodes
+    // Returns None if the walk reached a node that has been unlinked
+    fn node_or_insert(&self, index: &[Idx]) -> Option<Arc<ConcurrentNode<T, Idx>>> {
+        let mut node = Arc::clone(&self.head);
+        for key in index {
+            node = node.child_or_insert(key)?;
+        }
+
+        return Some(node);
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> Default for ConcurrentTreeMap<T, Idx> {
+    fn default() -> Self {
+        ConcurrentTreeMap::new()
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> FromIterator<(Vec<Idx>, T)> for ConcurrentTreeMap<T, Idx> {
+    fn from_iter<I: IntoIterator<Item = (Vec<Idx>, T)>>(iter: I) -> Self {
+        let map = ConcurrentTreeMap::new();
+        for (index, value) in iter {
+            map.insert(&index, value);
+        }
+
+        return map;
+    }
+}
+
+
+// Guards only exist for nodes that hold a value, and the value cannot be taken while the lock is held
+impl<T> Deref for ReadGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return self.guard.as_ref().unwrap();
+    }
+}
+
+
+impl<T> Deref for WriteGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return self.guard.as_ref().unwrap();
+    }
+}
+
+
+impl<T> DerefMut for WriteGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        return self.guard.as_mut().unwrap();
+    }
+}
+
+
+
+
+/*
+Entry API
+*/
+
+// Entry<'a, T>
+// A locked value in a ConcurrentTreeMap that may or may not exist
+//
+// This is created by ConcurrentTreeMap::entry and mirrors the TreeMap Entry
+// Both variants hold the value's write lock
+pub enum Entry<'a, T> {
+    Occupied(OccupiedEntry<T>),
+    Vacant(VacantEntry<'a, T>)
+}
+
+// An entry whose index holds a value
+pub struct OccupiedEntry<T> {
+    guard: WriteGuard<T>
+}
+
+// An entry whose index holds no value
+pub struct VacantEntry<'a, T> {
+    guard: WriteGuard<T>,
+
+    // The map's len counter, incremented when the entry is filled
+    len: &'a AtomicUsize
+}
+
+
+impl<'a, T> Entry<'a, T> {
+    // Ensures a value is in the entry by inserting the default if empty
+    // Returns a guard over the value
+    pub fn or_insert(self, default: T) -> WriteGuard<T> {
+        return match self {
+            Entry::Occupied(entry) => entry.into_guard(),
+            Entry::Vacant(entry) => entry.insert(default)
+        };
+    }
+
+    // Ensures a value is in the entry by inserting the result of default if empty
+    pub fn or_insert_with<F: FnOnce() -> T>(self, default: F) -> WriteGuard<T> {
+        return match self {
+            Entry::Occupied(entry) => entry.into_guard(),
+            Entry::Vacant(entry) => entry.insert(default())
+        };
+    }
+
+    // Ensures a value is in the entry by inserting T::default() if empty
+    pub fn or_default(self) -> WriteGuard<T> where T: Default {
+        return self.or_insert_with(T::default);
+    }
+
+    // Modifies the value in place if the entry is occupied
+    pub fn and_modify<F: FnOnce(&mut T)>(self, f: F) -> Self {
+        return match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            Entry::Vacant(entry) => Entry::Vacant(entry)
+        };
+    }
+}
+
+impl<T> OccupiedEntry<T> {
+    // Gets a reference to the value in the entry
+    pub fn get(&self) -> &T {
+        return &self.guard;
+    }
+
+    // Gets a mutable reference to the value in the entry
+    pub fn get_mut(&mut self) -> &mut T {
+        return &mut self.guard;
+    }
+
+    // Converts the entry into a guard over the value
+    pub fn into_guard(self) -> WriteGuard<T> {
+        return self.guard;
+    }
+
+    // Replaces the value in the entry and returns the old value
+    pub fn insert(&mut self, value: T) -> T {
+        return std::mem::replace(&mut self.guard, value);
+    }
+}
+
+impl<'a, T> VacantEntry<'a, T> {
+    // Inserts the value into the entry and returns a guard over it
+    pub fn insert(mut self, value: T) -> WriteGuard<T> {
+        *self.guard.guard = Some(value);
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        return self.guard;
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    Test: Insert and Find
+
+    Summary:
+        Checks inserting and finding values through guards and copies
+     */
+    #[test]
+    fn insert_find() {
+        let map: ConcurrentTreeMap<i32, &str> = ConcurrentTreeMap::new();
+
+        assert_eq!(map.insert(vec!["a", "b"].as_slice(), 1), None);
+        assert_eq!(map.insert(vec!["a", "c"].as_slice(), 2), None);
+        assert_eq!(map.insert(vec!["a", "b"].as_slice(), 3), Some(1));
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(*map.find(vec!["a", "b"].as_slice()).unwrap(), 3);
+        assert_eq!(map.find_cloned(vec!["a", "c"].as_slice()), Some(2));
+        assert!(map.find(vec!["a"].as_slice()).is_none());
+        assert!(map.find(vec!["x"].as_slice()).is_none());
+
+        *map.find_mut(vec!["a", "c"].as_slice()).unwrap() += 10;
+        assert_eq!(map.find_cloned(vec!["a", "c"].as_slice()), Some(12));
+    }
+
+    /*
+    Test: Entry
+
+    Summary:
+        Checks the entry API on vacant and occupied entries
+     */
+    #[test]
+    fn entry() {
+        let map: ConcurrentTreeMap<i32, &str> = ConcurrentTreeMap::new();
+
+        assert_eq!(*map.entry(vec!["a", "b"].as_slice()).or_insert(1), 1);
+        assert_eq!(*map.entry(vec!["a", "b"].as_slice()).or_insert(2), 1);
+        map.entry(vec!["a", "b"].as_slice()).and_modify(|v| *v += 10).or_insert(0);
+        map.entry(vec!["a", "c"].as_slice()).and_modify(|v| *v += 10).or_default();
+
+        if let Entry::Occupied(mut entry) = map.entry(vec!["a", "c"].as_slice()) {
+            assert_eq!(entry.insert(5), 0);
+        } else {
+            panic!("Expected an occupied entry");
+        }
+
+        assert_eq!(map.find_cloned(vec!["a", "b"].as_slice()), Some(11));
+        assert_eq!(map.find_cloned(vec!["a", "c"].as_slice()), Some(5));
+        assert_eq!(map.len(), 2);
+    }
+
+    /*
+    Test: Empty Index
+
+    Summary:
+        Checks that the empty index is stored in the root by insert and entry alike
+     */
+    #[test]
+    fn empty_index() {
+        let map: ConcurrentTreeMap<i32, &str> = ConcurrentTreeMap::new();
+        assert!(map.find(vec![].as_slice()).is_none());
+
+        assert_eq!(map.insert(vec![].as_slice(), 1), None);
+        assert_eq!(map.insert(vec![].as_slice(), 2), Some(1));
+        assert_eq!(*map.find(vec![].as_slice()).unwrap(), 2);
+        assert_eq!(map.len(), 1);
+
+        *map.entry(vec![].as_slice()).or_insert(0) += 10;
+        assert_eq!(map.find_cloned(vec![].as_slice()), Some(12));
+
+        // The root value doesn't affect the rest of the tree
+        map.insert(vec!["a"].as_slice(), 3);
+        assert_eq!(map.remove(vec![].as_slice()), Some(12));
+        assert!(map.find(vec![].as_slice()).is_none());
+        assert_eq!(map.find_cloned(vec!["a"].as_slice()), Some(3));
+        assert_eq!(map.len(), 1);
+
+        if let Entry::Vacant(entry) = map.entry(vec![].as_slice()) {
+            entry.insert(4);
+        } else {
+            panic!("Expected a vacant entry");
+        }
+        assert_eq!(map.len(), 2);
+
+        map.remove(vec!["a"].as_slice());
+        map.compact();
+        assert_eq!(map.find_cloned(vec![].as_slice()), Some(4));
+    }
+
+    /*
+    Test: Remove and Compact
+
+    Summary:
+        Checks that removing a value prunes the nodes left behind, and that compact prunes nodes
+        left by vacant entries and by values that were locked when remove reached them
+     */
+    #[test]
+    fn remove_compact() {
+        let map: ConcurrentTreeMap<i32, &str> = vec!(
+            (vec!["a", "b", "c"], 1),
+            (vec!["a", "d"], 2)
+        ).into_iter().collect();
+
+        assert_eq!(map.remove(vec!["a", "b", "c"].as_slice()), Some(1));
+        assert!(map.head.child(&"a").unwrap().child(&"b").is_none());
+        assert_eq!(map.remove(vec!["a", "b", "c"].as_slice()), None);
+        assert_eq!(map.remove(vec!["a", "b"].as_slice()), None);
+        assert!(map.find(vec!["a", "b", "c"].as_slice()).is_none());
+        assert_eq!(map.find_cloned(vec!["a", "d"].as_slice()), Some(2));
+        assert_eq!(map.len(), 1);
+
+        map.remove(vec!["a", "d"].as_slice());
+        assert!(map.is_empty());
+        assert!(map.head.nodes.read().is_empty());
+
+        // A vacant entry that is never filled leaves its path behind
+        drop(map.entry(vec!["x", "y"].as_slice()));
+        assert!(map.head.child(&"x").is_some());
+
+        // A node whose value is locked isn't pruned
+        let entry = map.entry(vec!["x", "y"].as_slice());
+        map.compact();
+        assert!(map.head.child(&"x").unwrap().child(&"y").is_some());
+
+        drop(entry);
+        map.compact();
+        assert!(map.head.nodes.read().is_empty());
+    }
+
+    /*
+    Test: Threads
+
+    Summary:
+        Updates shared counters and disjoint subtrees from several threads at once
+     */
+    #[test]
+    fn threads() {
+        let map: ConcurrentTreeMap<usize, String> = ConcurrentTreeMap::new();
+
+        // Miri interprets every step, so it runs fewer rounds
+        let rounds = if cfg!(miri) { 10 } else { 100 };
+
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let map = &map;
+                scope.spawn(move || {
+                    for i in 0..rounds {
+                        // Every thread updates the same counter
+                        *map.entry(vec!["total".to_string()].as_slice()).or_insert(0) += 1;
+
+                        // And writes to a subtree of its own
+                        map.insert(vec![thread.to_string(), i.to_string()].as_slice(), i);
+
+                        let found = map.find(vec![thread.to_string(), i.to_string()].as_slice());
+                        assert_eq!(found.as_deref(), Some(&i));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(map.find_cloned(vec!["total".to_string()].as_slice()), Some(8 * rounds));
+        assert_eq!(map.find_cloned(vec!["7".to_string(), "9".to_string()].as_slice()), Some(9));
+        assert_eq!(map.len(), 8 * rounds + 1);
+    }
+
+    /*
+    Test: Churn
+
+    Summary:
+        Inserts and removes values under shared prefixes from several threads at once
+        Pruning must never lose a value inserted concurrently, and the tree must not grow once the values are gone
+     */
+    #[test]
+    fn churn() {
+        let map: ConcurrentTreeMap<usize, usize> = ConcurrentTreeMap::new();
+
+        // Miri interprets every step, so it runs fewer rounds
+        let rounds = if cfg!(miri) { 15 } else { 200 };
+
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let map = &map;
+                scope.spawn(move || {
+                    for i in 0..rounds {
+                        // Every thread shares the first two keys of its paths with the others
+                        let index = [i % 3, i % 5, thread];
+
+                        map.insert(&index, i);
+                        assert_eq!(map.find_cloned(&index), Some(i));
+                        assert_eq!(map.remove(&index), Some(i));
+
+                        *map.entry(&index[..2]).or_insert(0) += 1;
+                    }
+                });
+            }
+        });
+
+        // Only the counters written through entry are left
+        let counters: usize = (0..15).map(|i| map.find_cloned(&[i % 3, i % 5]).unwrap()).sum();
+        assert_eq!(counters, 8 * rounds);
+        assert_eq!(map.len(), 15);
+
+        for i in 0..15 {
+            map.remove(&[i % 3, i % 5]);
+        }
+        assert!(map.is_empty());
+        assert!(map.head.nodes.read().is_empty());
+    }
+}
@@ -7,30 +7,33 @@ This is intended for internal use by StaticTreePlanner only
 This file will have left comments but is esentially copied from tree_map.rs
 */
 
+#[derive(Clone)]
 pub struct CountedTreeNode<T, Idx: PartialEq + Clone> {
-    pub key: Idx,
+    // The root node has no key
+    pub key: Option<Idx>,
 
     pub value: Option<T>,
 
     idx_counts: i32,
-    pub built_sub_nodes: i32,
+    pub built_sub_nodes: usize,
     pub visited: bool,
-    pub list_offset: i32,
+    // The index in the StaticTree pool of the node's first branch
+    pub list_offset: usize,
 
     pub nodes: Vec<CountedTreeNode<T, Idx>>
 }
 
 
-pub struct CountedTreeMap<T, Idx: PartialEq + Clone + Default> {
+#[derive(Clone)]
+pub struct CountedTreeMap<T, Idx: PartialEq + Clone> {
     head: Option<CountedTreeNode<T, Idx>>
 }
 
 
 impl<T, Idx: PartialEq + Clone> CountedTreeNode<T, Idx> {
-    pub fn insert(&mut self, index: &[Idx], value: T) {
+    pub fn insert(&mut self, index: &[Idx], value: T) -> Option<T> {
         if index.len() == 0 {
-            self.value = Some(value);
-            return;
+            return self.value.replace(value);
         }
         self.idx_counts += 1;
 
@@ -41,12 +44,12 @@ impl<T, Idx: PartialEq + Clone> CountedTreeNode<T, Idx> {
             Some(n) => n,
             None => {
                 let n = CountedTreeNode {
-                    key: (*key).clone(),
+                    key: Some((*key).clone()),
                     value: None,
                     idx_counts: 0,
                     built_sub_nodes: 0,
                     visited: false,
-                    list_offset: -1,
+                    list_offset: 0,
                     nodes: vec!()
                 };
 
@@ -56,7 +59,7 @@ impl<T, Idx: PartialEq + Clone> CountedTreeNode<T, Idx> {
             }
         };
 
-        next.insert(&index[1..], value);
+        return next.insert(&index[1..], value);
     }
 
     pub fn sort_nodes(&mut self) {
@@ -66,6 +69,47 @@ impl<T, Idx: PartialEq + Clone> CountedTreeNode<T, Idx> {
         }
     }
 
+    // Collects every (index, value) pair underneath this node in depth-first order
+    pub fn entries<'a>(&'a self, path: &mut Vec<Idx>, out: &mut Vec<(Vec<Idx>, &'a T)>) {
+        if let Some(value) = self.value.as_ref() {
+            out.push((path.clone(), value));
+        }
+
+        for node in &self.nodes {
+            path.push(node.key.clone().expect("Only the root node has no key"));
+            node.entries(path, out);
+            path.pop();
+        }
+    }
+
+    // Finds the value at the given index below this node
+    pub fn find(&self, index: &[Idx]) -> Option<&T> {
+        if index.len() == 0 {
+            return self.value.as_ref();
+        }
+
+        return self.find_node(&index[0])?.find(&index[1..]);
+    }
+
+    // Removes the value at the given index below this node
+    // The counts along the index are decremented and nodes that are emptied are pruned
+    pub fn remove(&mut self, index: &[Idx]) -> Option<T> {
+        if index.len() == 0 {
+            return self.value.take();
+        }
+
+        let position = self.nodes.iter().position(|node| node.key.as_ref() == Some(&index[0]))?;
+        let next = &mut self.nodes[position];
+        let value = next.remove(&index[1..])?;
+        self.idx_counts -= 1;
+
+        if next.value.is_none() && next.nodes.len() == 0 {
+            self.nodes.remove(position);
+        }
+
+        return Some(value);
+    }
+
     pub fn consume_next_node(&mut self) -> Option<CountedTreeNode<T, Idx>> {
         if self.nodes.len() == 0 {
             return None;
@@ -81,7 +125,7 @@ impl<T, Idx: PartialEq + Clone> CountedTreeNode<T, Idx> {
     #[allow(dead_code)] // Left here for future reference
     pub fn find_node(&self, key: &Idx) -> Option<&CountedTreeNode<T, Idx>> {
         for node in &self.nodes {
-            if node.key == *key {
+            if node.key.as_ref() == Some(key) {
                 return Some(node);
             }
         };
@@ -90,7 +134,7 @@ impl<T, Idx: PartialEq + Clone> CountedTreeNode<T, Idx> {
     }
     pub fn find_node_mut(&mut self, key: &Idx) -> Option<&mut CountedTreeNode<T, Idx>> {
         for node in &mut self.nodes {
-            if node.key == *key {
+            if node.key.as_ref() == Some(key) {
                 return Some(node);
             }
         };
@@ -130,25 +174,38 @@ impl<T, Idx: PartialEq + Clone> Ord for CountedTreeNode<T, Idx> {
 }
 
 
-impl<T, Idx: PartialEq + Clone + Default> CountedTreeMap<T, Idx> {
+impl<T, Idx: PartialEq + Clone> CountedTreeMap<T, Idx> {
     pub fn new() -> Self {
         CountedTreeMap {
             head: Some(CountedTreeNode {
-                key: Idx::default(),
+                key: None,
                 value: None,
                 built_sub_nodes: 0,
                 idx_counts: 0,
                 visited: false,
-                list_offset: -1,
+                list_offset: 0,
                 nodes: vec!()
             })
         }
     }
 
-    pub fn insert(&mut self, index: &[Idx], value: T) {
-        if index.len() == 0 { return; }
+    pub fn insert(&mut self, index: &[Idx], value: T) -> Option<T> {
+        return self.head.as_mut().unwrap().insert(index, value);
+    }
 
-        self.head.as_mut().unwrap().insert(index, value);
+    pub fn find(&self, index: &[Idx]) -> Option<&T> {
+        return self.root_ref().find(index);
+    }
+
+    pub fn remove(&mut self, index: &[Idx]) -> Option<T> {
+        return self.root_ref_mut().remove(index);
+    }
+
+    pub fn entries(&self) -> Vec<(Vec<Idx>, &T)> {
+        let mut out = vec!();
+        self.root_ref().entries(&mut vec!(), &mut out);
+
+        return out;
     }
 
     pub fn root(self) -> CountedTreeNode<T, Idx> {
@@ -161,11 +218,9 @@ impl<T, Idx: PartialEq + Clone + Default> CountedTreeMap<T, Idx> {
         return self.head.as_ref().unwrap();
     }
 }
-impl<T, Idx: PartialEq + Clone + Default> Default for CountedTreeMap<T, Idx> {
+impl<T, Idx: PartialEq + Clone> Default for CountedTreeMap<T, Idx> {
     fn default() -> Self {
-        CountedTreeMap {
-            head: None
-        }
+        CountedTreeMap::new()
     }
 }
 
@@ -176,6 +231,7 @@ impl<T, Idx: PartialEq + Clone + Default> Default for CountedTreeMap<T, Idx> {
 Tests:
     - Insert
     - Sort
+    - Remove
 */
 #[cfg(test)]
 mod tests {
@@ -235,4 +291,37 @@ mod tests {
         assert_eq!(node.idx_counts, 1);
     }
 
-}
\ No newline at end of file
+
+
+    /*
+    Test: Remove
+
+    Summary:
+        Checks that removing a value decrements the counts along its index and prunes emptied nodes
+     */
+    #[test]
+    fn remove() {
+        let mut map: CountedTreeMap<i32, &str> = CountedTreeMap::new();
+        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+        map.insert(vec!["a", "b", "d"].as_slice(), 2);
+        map.insert(vec!["a"].as_slice(), 3);
+
+        assert_eq!(map.remove(vec!["a", "b", "c"].as_slice()), Some(1));
+        assert_eq!(map.remove(vec!["a", "b", "c"].as_slice()), None);
+        assert_eq!(map.remove(vec!["a", "b"].as_slice()), None);
+
+        let root = map.root_ref();
+        assert_eq!(root.idx_counts, 2);
+        let node = root.find_node(&"a").unwrap().find_node(&"b").unwrap();
+        assert_eq!(node.idx_counts, 1);
+        assert!(node.find_node(&"c").is_none());
+
+        // "b" is emptied and pruned, but "a" still holds a value
+        assert_eq!(map.remove(vec!["a", "b", "d"].as_slice()), Some(2));
+        assert!(map.root_ref().find_node(&"a").unwrap().nodes.is_empty());
+
+        assert_eq!(map.remove(vec!["a"].as_slice()), Some(3));
+        assert!(map.root_ref().nodes.is_empty());
+        assert_eq!(map.root_ref().idx_counts, 0);
+    }
+}
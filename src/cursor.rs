@@ -0,0 +1,514 @@
+/*
+Summary:
+    The cursor module contains stateful cursors for navigating a TreeMap
+    A cursor points at a single node and can be moved up to its parent, down to a sub-node or across to a sibling
+
+    TreeNodes have no parent pointers so the cursors keep their own stack of the path they have taken
+    Siblings are ordered by the storage strategy of the tree, see the child_storage module
+*/
+use crate::child_storage::{ChildStorage, Children, Unsorted};
+use crate::tree_map::{TreeMap, TreeNode};
+
+
+// Cursor<'a, T, Idx, S>
+//
+// A readonly cursor over a TreeMap
+// This is created by TreeMap::cursor and starts at the root of the tree
+pub struct Cursor<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    // The node the cursor points at
+    current: &'a TreeNode<T, Idx, S>,
+
+    // The ancestors of the current node, starting with the root
+    stack: Vec<&'a TreeNode<T, Idx, S>>,
+
+    // The position of every node along the path within its parent's sub-nodes
+    // This is used to step between siblings without searching for the current key
+    positions: Vec<usize>,
+
+    // The index of the current node
+    path: Vec<Idx>
+}
+
+// CursorMut<'a, T, Idx, S>
+//
+// A mutable cursor over a TreeMap
+// Only one mutable reference into the tree can be held, so the cursor keeps the root and
+// walks back down to the current node using the recorded positions whenever it is accessed
+pub struct CursorMut<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    root: &'a mut TreeNode<T, Idx, S>,
+
+    // The value count of the map, which is updated as values are added and removed
+    len: &'a mut usize,
+
+    // The position of every node along the path within its parent's sub-nodes
+    positions: Vec<usize>,
+
+    // The index of the current node
+    path: Vec<Idx>
+}
+
+
+
+/* Cursor Implementation */
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Cursor<'a, T, Idx, S> {
+    // Creates a new cursor pointing at the root node
+    pub(crate) fn new(root: &'a TreeNode<T, Idx, S>) -> Self {
+        Cursor {
+            current: root,
+            stack: vec!(),
+            positions: vec!(),
+            path: vec!()
+        }
+    }
+
+    // Moves the cursor to the sub-node with the given key
+    // Returns false and leaves the cursor in place if the sub-node doesn't exist
+    pub fn descend(&mut self, key: &Idx) -> bool {
+        let position = match self.current.nodes.position(key) {
+            Some(p) => p,
+            None => { return false; }
+        };
+
+        return self.descend_to(position);
+    }
+
+    // Moves the cursor to the first sub-node of the current node
+    // Returns false if the current node has no sub-nodes
+    pub fn first_child(&mut self) -> bool {
+        return self.descend_to(0);
+    }
+
+    // Moves the cursor to the parent of the current node
+    // Returns false if the cursor is already at the root
+    pub fn ascend(&mut self) -> bool {
+        let parent = match self.stack.pop() {
+            Some(p) => p,
+            None => { return false; }
+        };
+
+        self.current = parent;
+        self.positions.pop();
+        self.path.pop();
+
+        return true;
+    }
+
+    // Moves the cursor to the next sibling of the current node
+    // Returns false if there is no next sibling or the cursor is at the root
+    pub fn next_sibling(&mut self) -> bool {
+        let position = match self.positions.last() {
+            Some(p) => *p + 1,
+            None => { return false; }
+        };
+
+        return self.move_to_sibling(position);
+    }
+
+    // Moves the cursor to the previous sibling of the current node
+    // Returns false if there is no previous sibling or the cursor is at the root
+    pub fn prev_sibling(&mut self) -> bool {
+        let position = match self.positions.last() {
+            Some(p) if *p > 0 => *p - 1,
+            _ => { return false; }
+        };
+
+        return self.move_to_sibling(position);
+    }
+
+    // Returns the value held by the current node
+    pub fn value(&self) -> Option<&'a T> {
+        return self.current.value.as_ref();
+    }
+
+    // Returns the key of the current node
+    // The root has no key so None is returned
+    pub fn key(&self) -> Option<&Idx> {
+        return self.path.last();
+    }
+
+    // Returns the full index of the current node
+    pub fn current_path(&self) -> &[Idx] {
+        return &self.path;
+    }
+
+    // Returns the number of keys between the root and the current node
+    pub fn depth(&self) -> usize {
+        return self.path.len();
+    }
+
+
+
+    /* Helper Methods */
+    // Moves to the sub-node at the given position
+    fn descend_to(&mut self, position: usize) -> bool {
+        let (key, node) = match self.current.nodes.get_index(position) {
+            Some(n) => n,
+            None => { return false; }
+        };
+
+        self.stack.push(self.current);
+        self.positions.push(position);
+        self.path.push(key.clone());
+        self.current = node;
+
+        return true;
+    }
+
+    // Replaces the current node with the sibling at the given position
+    fn move_to_sibling(&mut self, position: usize) -> bool {
+        let parent = self.stack.last().unwrap();
+        let (key, node) = match parent.nodes.get_index(position) {
+            Some(n) => n,
+            None => { return false; }
+        };
+
+        *self.positions.last_mut().unwrap() = position;
+        *self.path.last_mut().unwrap() = key.clone();
+        self.current = node;
+
+        return true;
+    }
+}
+
+
+
+/* CursorMut Implementation */
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> CursorMut<'a, T, Idx, S> {
+    // Creates a new cursor pointing at the root node
+    pub(crate) fn new(root: &'a mut TreeNode<T, Idx, S>, len: &'a mut usize) -> Self {
+        CursorMut {
+            root,
+            len,
+            positions: vec!(),
+            path: vec!()
+        }
+    }
+
+    // Moves the cursor to the sub-node with the given key
+    // Returns false and leaves the cursor in place if the sub-node doesn't exist
+    pub fn descend(&mut self, key: &Idx) -> bool {
+        let position = match self.current().nodes.position(key) {
+            Some(p) => p,
+            None => { return false; }
+        };
+
+        return self.descend_to(position);
+    }
+
+    // Moves the cursor to the first sub-node of the current node
+    // Returns false if the current node has no sub-nodes
+    pub fn first_child(&mut self) -> bool {
+        return self.descend_to(0);
+    }
+
+    // Moves the cursor to the parent of the current node
+    // Returns false if the cursor is already at the root
+    pub fn ascend(&mut self) -> bool {
+        if self.positions.pop().is_none() { return false; }
+
+        self.path.pop();
+
+        return true;
+    }
+
+    // Moves the cursor to the next sibling of the current node
+    // Returns false if there is no next sibling or the cursor is at the root
+    pub fn next_sibling(&mut self) -> bool {
+        let position = match self.positions.last() {
+            Some(p) => *p + 1,
+            None => { return false; }
+        };
+
+        return self.move_to_sibling(position);
+    }
+
+    // Moves the cursor to the previous sibling of the current node
+    // Returns false if there is no previous sibling or the cursor is at the root
+    pub fn prev_sibling(&mut self) -> bool {
+        let position = match self.positions.last() {
+            Some(p) if *p > 0 => *p - 1,
+            _ => { return false; }
+        };
+
+        return self.move_to_sibling(position);
+    }
+
+    // Returns the value held by the current node
+    pub fn value(&self) -> Option<&T> {
+        return self.current().value.as_ref();
+    }
+
+    // Mutable counterpart of value
+    pub fn value_mut(&mut self) -> Option<&mut T> {
+        return self.current_mut().value.as_mut();
+    }
+
+    // Sets the value of the current node and returns the old value
+    // At the root this sets the value of the empty index
+    pub fn set_value(&mut self, value: T) -> Option<T> {
+        let old = self.current_mut().value.replace(value);
+        if old.is_none() { *self.len += 1; }
+
+        return old;
+    }
+
+    // Returns the key of the current node
+    // The root has no key so None is returned
+    pub fn key(&self) -> Option<&Idx> {
+        return self.path.last();
+    }
+
+    // Returns the full index of the current node
+    pub fn current_path(&self) -> &[Idx] {
+        return &self.path;
+    }
+
+    // Returns the number of keys between the root and the current node
+    pub fn depth(&self) -> usize {
+        return self.path.len();
+    }
+
+    // Inserts a value into the sub-node of the current node with the given key
+    // The sub-node is created if it doesn't exist, otherwise its value is replaced and the old value returned
+    // The cursor doesn't move
+    pub fn insert_child(&mut self, key: Idx, value: T) -> Option<T> {
+        // Sub-nodes of the current node aren't on the path, so the recorded positions stay valid
+        let old = self.current_mut().nodes.get_or_insert(&key).value.replace(value);
+        if old.is_none() { *self.len += 1; }
+
+        return old;
+    }
+
+    // Removes the current node and everything underneath it and returns it as a new TreeMap
+    // Paths in the returned map are relative to the removed node
+    //
+    // The cursor moves to the parent of the removed node
+    // Ancestors that are left without a value or sub-nodes are pruned and the cursor moves above them
+    // The root can't be removed so None is returned if the cursor is at the root
+    pub fn remove_current(&mut self) -> Option<TreeMap<T, Idx, S>> {
+        let key = self.path.pop()?;
+        self.positions.pop();
+
+        let removed = self.current_mut().nodes.remove(&key)?;
+
+        // Prune emptied ancestors, stopping at the root
+        while self.positions.len() > 0 && self.current().is_empty() {
+            let key = self.path.pop().unwrap();
+            self.positions.pop();
+
+            self.current_mut().nodes.remove(&key);
+        }
+
+        let removed = TreeMap::from_root(removed);
+        *self.len -= removed.len();
+
+        return Some(removed);
+    }
+
+
+
+    /* Helper Methods */
+    // Walks from the root to the current node
+    fn current(&self) -> &TreeNode<T, Idx, S> {
+        let mut node: &TreeNode<T, Idx, S> = self.root;
+        for position in &self.positions {
+            node = node.nodes.get_index(*position).unwrap().1;
+        }
+
+        return node;
+    }
+
+    // Mutable counterpart of current
+    fn current_mut(&mut self) -> &mut TreeNode<T, Idx, S> {
+        let mut node: &mut TreeNode<T, Idx, S> = self.root;
+        for position in &self.positions {
+            node = node.nodes.get_index_mut(*position).unwrap().1;
+        }
+
+        return node;
+    }
+
+    // Walks from the root to the parent of the current node
+    fn parent(&self) -> Option<&TreeNode<T, Idx, S>> {
+        let (_, ancestors) = self.positions.split_last()?;
+
+        let mut node: &TreeNode<T, Idx, S> = self.root;
+        for position in ancestors {
+            node = node.nodes.get_index(*position).unwrap().1;
+        }
+
+        return Some(node);
+    }
+
+    // Moves to the sub-node at the given position
+    fn descend_to(&mut self, position: usize) -> bool {
+        let key = match self.current().nodes.get_index(position) {
+            Some((key, _)) => key.clone(),
+            None => { return false; }
+        };
+
+        self.positions.push(position);
+        self.path.push(key);
+
+        return true;
+    }
+
+    // Replaces the current node with the sibling at the given position
+    fn move_to_sibling(&mut self, position: usize) -> bool {
+        let parent = self.parent().unwrap();
+        let key = match parent.nodes.get_index(position) {
+            Some((key, _)) => key.clone(),
+            None => { return false; }
+        };
+
+        *self.positions.last_mut().unwrap() = position;
+        *self.path.last_mut().unwrap() = key;
+
+        return true;
+    }
+}
+
+
+
+
+/*
+Tests:
+    - Cursor        Descending, ascending and moving between siblings
+    - CursorMut     Modifying values, inserting sub-nodes and removing nodes with pruning
+*/
+#[cfg(test)]
+mod tests {
+    use crate::{TreeMap, Sorted};
+
+    // Helper function for building the tree used by the cursor tests
+    fn cursor_map() -> TreeMap<i32, &'static str> {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a"].as_slice(), 1);
+        map.insert(vec!["a", "b"].as_slice(), 2);
+        map.insert(vec!["a", "c", "d"].as_slice(), 3);
+        map.insert(vec!["a", "e"].as_slice(), 4);
+        map.insert(vec!["f"].as_slice(), 5);
+
+        return map;
+    }
+
+
+    /*
+    Test: Cursor
+
+    Summary:
+        Checks that the cursor tracks its path and value as it moves around the tree
+     */
+    #[test]
+    fn cursor() {
+        let map = cursor_map();
+        let mut cursor = map.cursor();
+
+        // Root
+        assert!(cursor.value().is_none());
+        assert!(cursor.key().is_none());
+        assert!(!cursor.ascend());
+        assert!(!cursor.next_sibling());
+
+        assert!(cursor.descend(&"a"));
+        assert_eq!(cursor.value(), Some(&1));
+        assert!(!cursor.descend(&"z"));
+        assert_eq!(cursor.current_path(), &["a"]);
+
+        // Siblings
+        assert!(cursor.first_child());
+        assert_eq!(cursor.current_path(), &["a", "b"]);
+        assert!(!cursor.prev_sibling());
+        assert!(cursor.next_sibling());
+        assert_eq!(cursor.current_path(), &["a", "c"]);
+        assert!(cursor.value().is_none());
+        assert!(cursor.next_sibling());
+        assert_eq!(cursor.value(), Some(&4));
+        assert!(!cursor.next_sibling());
+        assert!(cursor.prev_sibling());
+        assert_eq!(cursor.key(), Some(&"c"));
+
+        assert!(cursor.descend(&"d"));
+        assert_eq!(cursor.value(), Some(&3));
+        assert_eq!(cursor.depth(), 3);
+
+        // Back up to the root level
+        assert!(cursor.ascend());
+        assert!(cursor.ascend());
+        assert!(cursor.next_sibling());
+        assert_eq!(cursor.current_path(), &["f"]);
+        assert_eq!(cursor.value(), Some(&5));
+    }
+
+
+    /*
+    Test: Cursor Mut
+
+    Summary:
+        Checks that the mutable cursor can modify values, insert sub-nodes and remove nodes
+     */
+    #[test]
+    fn cursor_mut() {
+        let mut map = cursor_map();
+
+        {
+            let mut cursor = map.cursor_mut();
+            assert!(cursor.descend(&"a"));
+            *cursor.value_mut().unwrap() += 10;
+
+            // Insert without moving
+            assert!(cursor.insert_child("g", 6).is_none());
+            assert_eq!(cursor.insert_child("b", 7), Some(2));
+            assert_eq!(cursor.current_path(), &["a"]);
+
+            // Remove a subtree, the cursor moves to the parent
+            assert!(cursor.descend(&"c"));
+            assert!(cursor.descend(&"d"));
+            let removed = cursor.remove_current().unwrap();
+            let removed: Vec<(Vec<&str>, &i32)> = removed.iter().collect();
+            assert_eq!(removed, vec!((vec!(), &3)));
+
+            // "c" is left empty so is pruned as well
+            assert_eq!(cursor.current_path(), &["a"]);
+            assert!(!cursor.descend(&"c"));
+        }
+
+        assert_eq!(*map.find(vec!["a"].as_slice()).unwrap(), 11);
+        assert_eq!(*map.find(vec!["a", "b"].as_slice()).unwrap(), 7);
+        assert_eq!(*map.find(vec!["a", "g"].as_slice()).unwrap(), 6);
+        assert!(map.find(vec!["a", "c", "d"].as_slice()).is_none());
+        assert!(map.subtree(vec!["a", "c"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Cursor Sorted
+
+    Summary:
+        Siblings of a Sorted tree are visited in key order and stay valid after inserting sub-nodes
+     */
+    #[test]
+    fn cursor_sorted() {
+        let mut map: TreeMap<i32, i32, Sorted> = TreeMap::new();
+        map.insert(vec![5].as_slice(), 5);
+        map.insert(vec![1].as_slice(), 1);
+        map.insert(vec![3].as_slice(), 3);
+
+        let mut cursor = map.cursor_mut();
+        assert!(cursor.descend(&3));
+        cursor.insert_child(0, 30);
+
+        let mut keys = vec!();
+        assert!(cursor.ascend());
+        assert!(cursor.first_child());
+        loop {
+            keys.push(*cursor.key().unwrap());
+            if !cursor.next_sibling() { break; }
+        }
+
+        assert_eq!(keys, vec!(1, 3, 5));
+        assert!(cursor.prev_sibling());
+        assert!(cursor.descend(&0));
+        assert_eq!(cursor.value(), Some(&30));
+    }
+}
@@ -2,16 +2,24 @@
 Author: Matthew Tindley
 
 Summary:
-    The DynamicArray type is used to create a block of n bytes memory that can be 
+    The DynamicArray type is used to create a block of n bytes memory that can be
     referenced and cast freely
+
+    The block is allocated with the alignment given by its Layout, so any type with an alignment
+    no greater than the Layout's can be stored at offsets that are a multiple of its own alignment
+    The memory starts uninitialised. Values are moved in with write, and reading is unsafe as the
+    caller must know which type was written at an offset
+    Freeing the array doesn't drop its contents. The owner must drop them with drop_in_place
 */
 
-use std::alloc::{alloc, dealloc, Layout};
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::ptr::NonNull;
 
 
 pub struct DynamicArray {
     // The base memory pointer
-    mem: *mut u8,
+    // This is dangling, but still aligned, if the shape has a size of 0
+    mem: NonNull<u8>,
 
     // The shape of the memory
     // This is given by a Layout object which holds the raw size and alignment
@@ -23,35 +31,99 @@ pub struct DynamicArray {
 impl DynamicArray {
 
     // Creates a new DynamicArray type
-    // This function allocates raw memory
+    // This function allocates raw memory and aborts through handle_alloc_error if the allocation fails
     pub fn new(layout: Layout) -> Self {
-        unsafe {
-            DynamicArray {
-                mem: alloc(layout),
-                shape: layout
-            }
+        // Allocating zero bytes is undefined behaviour, so an empty array never allocates
+        let mem = if layout.size() == 0 {
+            // The address is the alignment, so the pointer is aligned but never dereferenced
+            NonNull::new(layout.align() as *mut u8).unwrap()
+        } else {
+            // SAFETY: The layout has a non-zero size
+            let mem = unsafe { alloc(layout) };
+            NonNull::new(mem).unwrap_or_else(|| handle_alloc_error(layout))
+        };
+
+        DynamicArray {
+            mem,
+            shape: layout
         }
     }
 
-    // Takes memory from the offset and casts it into T
-    pub fn get<T>(&self, offset: usize) -> &T {
-        unsafe {
-            return &(*self.mem.offset(offset as isize).cast::<T>());
+    // Takes over the allocation of a boxed slice, so values built elsewhere can become the array without copying
+    // The array has the slice's layout and its memory holds the slice's elements, which are not dropped when it is freed
+    pub fn from_boxed_slice<T>(slice: Box<[T]>) -> Self {
+        let layout = Layout::for_value(&*slice);
+
+        DynamicArray {
+            // A Box is never null. An empty slice is dangling but aligned, and isn't freed as its size is 0
+            mem: NonNull::new(Box::into_raw(slice).cast::<u8>()).unwrap(),
+            shape: layout
         }
     }
+
+    // Takes memory from the offset and casts it into T
+    //
+    // SAFETY: A T must have been written at the offset, and the memory must not be mutably borrowed
+    pub unsafe fn get<T>(&self, offset: usize) -> &T {
+        debug_assert!(self.fits::<T>(offset));
+
+        return &*self.mem.as_ptr().add(offset).cast::<T>();
+    }
+
     // Same as get but mutable
-    pub fn get_mut<T>(&self, offset: usize) -> &mut T {
+    // Exclusive access comes from the &mut self borrow so no other reference into the array can be alive
+    //
+    // SAFETY: A T must have been written at the offset
+    pub unsafe fn get_mut<T>(&mut self, offset: usize) -> &mut T {
+        debug_assert!(self.fits::<T>(offset));
+
+        return &mut *self.mem.as_ptr().add(offset).cast::<T>();
+    }
+
+    // Moves value into the memory at the offset without reading or dropping what was there
+    // This must be used to initialise memory, as assigning through get_mut would drop the uninitialised contents
+    // Panics if a T doesn't fit at the offset
+    pub fn write<T>(&mut self, offset: usize, value: T) {
+        assert!(self.fits::<T>(offset), "DynamicArray::write out of bounds or misaligned");
+
+        // SAFETY: The offset is in bounds and aligned for T, and the &mut self borrow means nothing else is reading it
         unsafe {
-            return &mut (*self.mem.offset(offset as isize).cast::<T>());
+            std::ptr::write(self.mem.as_ptr().add(offset).cast::<T>(), value);
         }
     }
+
+
+
+    // Runs the destructor of the T at the offset, leaving the memory uninitialised
+    //
+    // SAFETY: A T must have been written at the offset, and it must not be read or dropped again
+    pub unsafe fn drop_in_place<T>(&mut self, offset: usize) {
+        debug_assert!(self.fits::<T>(offset));
+
+        std::ptr::drop_in_place(self.mem.as_ptr().add(offset).cast::<T>());
+    }
+
+
+
+    /* Helper Methods */
+    // Checks that a T at the offset lies within the array and is correctly aligned
+    fn fits<T>(&self, offset: usize) -> bool {
+        let in_bounds = offset.checked_add(std::mem::size_of::<T>()).is_some_and(|end| end <= self.shape.size());
+        let aligned = std::mem::align_of::<T>() <= self.shape.align() && offset % std::mem::align_of::<T>() == 0;
+
+        return in_bounds && aligned;
+    }
 }
 
 // Auto free memory when the type is dropped
 impl Drop for DynamicArray {
     fn drop(&mut self) {
+        if self.shape.size() == 0 { return; }
+
+        // SAFETY: The memory was allocated by the global allocator with the same layout,
+        // either in new or by the Box passed to from_boxed_slice
         unsafe {
-            dealloc(self.mem, self.shape);
+            dealloc(self.mem.as_ptr(), self.shape);
         }
     }
 }
@@ -62,6 +134,8 @@ impl Drop for DynamicArray {
 /*
 Tests:
     - Allocate
+    - Read and write
+    - From boxed slice
 */
 #[cfg(test)]
 mod tests {
@@ -74,5 +148,56 @@ mod tests {
 
         // Drop Array
         drop(arr);
+
+        // Empty arrays don't allocate
+        let arr = DynamicArray::new(Layout::from_size_align(0, 8).unwrap());
+        drop(arr);
+    }
+
+    /*
+    Test: Read and Write
+
+    Summary:
+        Checks that aligned values can be written, read and mutated, and that misaligned writes are rejected
+     */
+    #[test]
+    fn read_write() {
+        let mut arr = DynamicArray::new(Layout::from_size_align(16, 8).unwrap());
+
+        arr.write(0, 1u64);
+        arr.write(8, 2u32);
+        arr.write(12, 3u32);
+
+        unsafe {
+            *arr.get_mut::<u32>(8) += 10;
+
+            assert_eq!(*arr.get::<u64>(0), 1);
+            assert_eq!(*arr.get::<u32>(8), 12);
+            assert_eq!(*arr.get::<u32>(12), 3);
+        }
+
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arr.write(4, 0u64))).is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arr.write(16, 0u8))).is_err());
     }
-}
\ No newline at end of file
+    /*
+    Test: From Boxed Slice
+
+    Summary:
+        Checks that a boxed slice's elements can be read from the array and that its memory is freed
+     */
+    #[test]
+    fn from_boxed_slice() {
+        let mut arr = DynamicArray::from_boxed_slice(vec!(1u32, 2, 3).into_boxed_slice());
+        arr.write(4, 20u32);
+
+        unsafe {
+            assert_eq!(*arr.get::<u32>(0), 1);
+            assert_eq!(*arr.get::<u32>(4), 20);
+            assert_eq!(*arr.get::<u32>(8), 3);
+        }
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| arr.write(12, 0u32))).is_err());
+
+        // Empty slices don't own an allocation
+        drop(DynamicArray::from_boxed_slice(Vec::<u64>::new().into_boxed_slice()));
+    }
+}
@@ -23,18 +23,44 @@ LICENSE:
     SOFTWARE.
 */
 
+// The crate is written with explicit returns and len() == 0 checks throughout
+// The remaining lints are for the original planner and counted tree code, which is kept as written
+#![allow(
+    clippy::needless_return, clippy::len_zero,
+    clippy::manual_find, clippy::get_first, clippy::non_canonical_partial_ord_impl, clippy::bool_assert_comparison
+)]
+
+
 /* Public Module Declarations */
 pub mod tree_map;
 pub mod static_tree;
 pub mod static_tree_planner;
+pub mod pattern;
+pub mod path;
+pub mod child_storage;
+pub mod cursor;
+pub mod arena_tree_map;
+pub mod persistent_tree_map;
+pub mod concurrent_tree_map;
+pub mod radix_tree_map;
+pub mod binary;
 
 
 /* Public Imports */
 pub use tree_map::TreeMap;
 pub use static_tree_planner::StaticTreePlanner;
 pub use static_tree::StaticTree;
+pub use pattern::PatternSegment;
+pub use child_storage::{ Unsorted, Sorted, Hashed };
+pub use arena_tree_map::ArenaTreeMap;
+pub use persistent_tree_map::PersistentTreeMap;
+pub use concurrent_tree_map::ConcurrentTreeMap;
+pub use radix_tree_map::RadixTreeMap;
+pub use binary::StaticPod;
 
 
 /* Internal Module Declarations */
 mod dynamic_array;
-mod counted_tree_map;
\ No newline at end of file
+mod counted_tree_map;
+#[cfg(feature = "serde")]
+mod serialize;
\ No newline at end of file
@@ -0,0 +1,104 @@
+/*
+Summary:
+    The path module converts between delimited path strings and the String segments used as tree indices
+    This backs the *_path methods on TreeMap, StaticTreePlanner and StaticTree
+
+    Segments are separated by a single separator character, such as '/' in "a/b/c"
+    A backslash escapes the character that follows it, so "a\/b" is the single segment "a/b"
+    and "a\\b" is the single segment "a\b". The empty string is the empty path
+
+Tests:
+    - Split
+    - Join
+*/
+
+
+// The character used to escape separators and itself within a path
+const ESCAPE: char = '\\';
+
+
+// Splits a delimited path into its segments, removing escapes
+// Empty segments are kept, so "a//b" is split into "a", "" and "b"
+// A trailing backslash with nothing to escape is kept as a literal backslash
+pub fn split(path: &str, separator: char) -> Vec<String> {
+    if path.len() == 0 { return vec!(); }
+
+    let mut segments = vec!();
+    let mut current = String::new();
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c == ESCAPE {
+            current.push(chars.next().unwrap_or(ESCAPE));
+        } else if c == separator {
+            segments.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    segments.push(current);
+
+    return segments;
+}
+
+
+// Joins segments into a delimited path, escaping any separators and backslashes within them
+// This is the inverse of split for every index except a single empty segment,
+// which joins to the empty string and so splits back into the empty path
+pub fn join<S: AsRef<str>>(segments: &[S], separator: char) -> String {
+    let mut path = String::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 { path.push(separator); }
+
+        for c in segment.as_ref().chars() {
+            if c == separator || c == ESCAPE {
+                path.push(ESCAPE);
+            }
+            path.push(c);
+        }
+    }
+
+    return path;
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    Test: Split
+
+    Summary:
+        Checks splitting paths with empty segments and escapes
+     */
+    #[test]
+    fn split_path() {
+        assert_eq!(split("a/b/c", '/'), vec!["a", "b", "c"]);
+        assert_eq!(split("a.b", '.'), vec!["a", "b"]);
+        assert_eq!(split("a/b", '.'), vec!["a/b"]);
+        assert_eq!(split("", '/'), Vec::<String>::new());
+        assert_eq!(split("/a//b/", '/'), vec!["", "a", "", "b", ""]);
+        assert_eq!(split(r"a\/b/c", '/'), vec!["a/b", "c"]);
+        assert_eq!(split(r"a\\/b", '/'), vec![r"a\", "b"]);
+        assert_eq!(split(r"a\", '/'), vec![r"a\"]);
+    }
+
+    /*
+    Test: Join
+
+    Summary:
+        Checks that joining escapes separators and splits back into the same segments
+     */
+    #[test]
+    fn join_path() {
+        assert_eq!(join(&["a", "b", "c"], '/'), "a/b/c");
+        assert_eq!(join(&["a/b", r"c\"], '/'), r"a\/b/c\\");
+        assert_eq!(join::<&str>(&[], '/'), "");
+
+        let segments = vec!["x.y", "", r"\", "z"];
+        assert_eq!(split(&join(&segments, '.'), '.'), segments);
+    }
+}
@@ -0,0 +1,37 @@
+/*
+Summary:
+    The pattern module contains the segment type used to query trees with wildcards
+    Patterns follow the style of MQTT topic filters, where + matches a single level and # matches every level below it
+*/
+
+
+// PatternSegment<Idx>
+// Idx - The index type of the tree being queried
+//
+// A single segment of a pattern query
+// A pattern is given as a slice of segments, one for each depth of the tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatternSegment<Idx> {
+    // Matches a node with exactly this key
+    Key(Idx),
+
+    // Matches any single key at this depth
+    // This is the equivalent of the MQTT + wildcard
+    Single,
+
+    // Matches the node at this depth and every node below it
+    // This is the equivalent of the MQTT # wildcard and must be the last segment in a pattern
+    Multi
+}
+
+
+/* Helper Functions */
+// Checks that a Multi segment only appears at the end of a pattern
+// Patterns that break this rule are a programming error so this panics
+pub(crate) fn validate<Idx>(pattern: &[PatternSegment<Idx>]) {
+    let position = pattern.iter().position(|segment| matches!(segment, PatternSegment::Multi));
+
+    if let Some(position) = position {
+        assert!(position == pattern.len() - 1, "PatternSegment::Multi must be the last segment of a pattern");
+    }
+}
@@ -0,0 +1,449 @@
+/*
+Summary:
+    The persistent_tree_map module contains an immutable, structurally shared variant of the TreeMap
+    Every node is held behind an Arc. Inserting or removing a value copies only the nodes along the
+    path to that value and returns a new version of the tree. Every untouched subtree is shared
+    between the old and new versions
+
+    Cloning a PersistentTreeMap only clones the root Arc, so taking a snapshot is O(1)
+    Versions can be sent to and read from other threads when T and Idx are Send and Sync
+
+Tests:
+    - Insert and find
+    - Remove
+    - Empty index
+    - Structural sharing
+    - Snapshot across threads
+*/
+use std::fmt::Debug;
+use std::sync::Arc;
+
+
+// PersistentNode<T, Idx>
+// T - The value to store
+// Idx - The index type. Must be equatable and clonable
+//
+// A node of a PersistentTreeMap
+// Nodes are never mutated once they are shared; changes build new nodes instead
+struct PersistentNode<T, Idx> {
+    // The value stored by the node
+    // Values are behind an Arc so that copying a node does not require T: Clone
+    value: Option<Arc<T>>,
+
+    // The shared sub-nodes underneath this node, each with its key at this depth
+    nodes: Vec<(Idx, Arc<PersistentNode<T, Idx>>)>
+}
+
+
+// The copy of a node left after a removal, or None if it was pruned, along with the removed value
+type Removed<T, Idx> = (Option<PersistentNode<T, Idx>>, Arc<T>);
+
+
+// PersistentTreeMap<T, Idx>
+// T - The value type to store
+// Idx - The index type. Must be equatable and clonable
+//
+// An immutable tree map where every change returns a new version
+// The root holds no key and holds the value of the empty index
+pub struct PersistentTreeMap<T, Idx: PartialEq + Clone> {
+    // The top-most node of this version
+    head: Arc<PersistentNode<T, Idx>>,
+
+    // The number of values stored in this version
+    len: usize
+}
+
+
+// Iter<'a, T, Idx>
+// Iterates over the (index, value) pairs of a PersistentTreeMap in depth-first order
+pub struct Iter<'a, T, Idx: PartialEq + Clone> {
+    // The value of the empty index, which is yielded before any node
+    root: Option<&'a T>,
+
+    // The nodes still to visit, each with the depth of the node
+    stack: Vec<(&'a Idx, &'a PersistentNode<T, Idx>, usize)>,
+
+    // The keys that lead to the node most recently visited
+    path: Vec<Idx>
+}
+
+
+
+
+/*
+Implementations
+*/
+
+
+impl<T, Idx: PartialEq + Clone> PersistentNode<T, Idx> {
+    fn new() -> Self {
+        PersistentNode {
+            value: None,
+            nodes: vec!()
+        }
+    }
+
+    fn find(&self, index: &[Idx]) -> Option<&T> {
+        if index.len() == 0 {
+            return self.value.as_deref();
+        }
+
+        return self.find_node(&index[0])?.find(&index[1..]);
+    }
+
+    // Returns a copy of this node with the value at index set
+    // Only the nodes along index are copied. The old value, if any, is returned alongside
+    fn insert(&self, index: &[Idx], value: Arc<T>) -> (PersistentNode<T, Idx>, Option<Arc<T>>) {
+        if index.len() == 0 {
+            let node = PersistentNode {
+                value: Some(value),
+                nodes: self.nodes.clone()
+            };
+
+            return (node, self.value.clone());
+        }
+
+        let mut nodes = self.nodes.clone();
+        let old = match nodes.iter().position(|(key, _)| *key == index[0]) {
+            Some(i) => {
+                let (node, old) = nodes[i].1.insert(&index[1..], value);
+                nodes[i].1 = Arc::new(node);
+
+                old
+            },
+            None => {
+                let (node, _) = PersistentNode::new().insert(&index[1..], value);
+                nodes.push((index[0].clone(), Arc::new(node)));
+
+                None
+            }
+        };
+
+        let node = PersistentNode {
+            value: self.value.clone(),
+            nodes
+        };
+
+        return (node, old);
+    }
+
+    // Returns a copy of this node with the value at index removed, along with the removed value
+    // The copy is None if it would be left without a value or sub-nodes
+    // Returns None if there is no value at index, in which case nothing needs to be copied
+    fn remove(&self, index: &[Idx]) -> Option<Removed<T, Idx>> {
+        let (value, nodes, old) = if index.len() == 0 {
+            let old = self.value.clone()?;
+
+            (None, self.nodes.clone(), old)
+        } else {
+            let i = self.nodes.iter().position(|(key, _)| *key == index[0])?;
+            let (node, old) = self.nodes[i].1.remove(&index[1..])?;
+
+            let mut nodes = self.nodes.clone();
+            match node {
+                Some(node) => nodes[i].1 = Arc::new(node),
+                None => { nodes.remove(i); }
+            }
+
+            (self.value.clone(), nodes, old)
+        };
+
+        // Prune nodes that no longer lead to a value
+        if value.is_none() && nodes.len() == 0 {
+            return Some((None, old));
+        }
+
+        return Some((Some(PersistentNode { value, nodes }), old));
+    }
+
+    fn find_node(&self, key: &Idx) -> Option<&PersistentNode<T, Idx>> {
+        return self.nodes.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, node)| node.as_ref());
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> PersistentTreeMap<T, Idx> {
+    pub fn new() -> Self {
+        PersistentTreeMap {
+            head: Arc::new(PersistentNode::new()),
+            len: 0
+        }
+    }
+
+    // Returns a new version of the tree with the value inserted
+    // If the index already holds a value it is replaced in the new version only
+    // The empty index is stored in the root node
+    pub fn insert(&self, index: &[Idx], value: T) -> Self {
+        let (head, old) = self.head.insert(index, Arc::new(value));
+
+        return PersistentTreeMap {
+            head: Arc::new(head),
+            len: if old.is_none() { self.len + 1 } else { self.len }
+        };
+    }
+
+    // Returns a new version of the tree with the value at index removed
+    // If there is no value at index an unchanged version is returned
+    pub fn remove(&self, index: &[Idx]) -> Self {
+        return match self.head.remove(index) {
+            Some((head, _)) => PersistentTreeMap {
+                head: Arc::new(head.unwrap_or_else(PersistentNode::new)),
+                len: self.len - 1
+            },
+            None => self.clone()
+        };
+    }
+
+    // Finds and returns a value from this version of the tree
+    // If the value exists then a reference to it is returned
+    // Otherwise None is returned
+    pub fn find(&self, index: &[Idx]) -> Option<&T> {
+        return self.head.find(index);
+    }
+
+    // Returns the number of values stored in this version
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    // Returns true if this version holds no values
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    // Returns true if both versions share the same root, and therefore hold the same values
+    pub fn ptr_eq(&self, other: &PersistentTreeMap<T, Idx>) -> bool {
+        return Arc::ptr_eq(&self.head, &other.head);
+    }
+
+    // Returns a depth-first iterator over every (index, value) pair in this version
+    // The value of the empty index comes first
+    pub fn iter(&self) -> Iter<'_, T, Idx> {
+        return Iter {
+            root: self.head.value.as_deref(),
+            stack: self.head.nodes.iter().rev().map(|(key, node)| (key, node.as_ref(), 0)).collect(),
+            path: vec!()
+        };
+    }
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone> Iterator for Iter<'a, T, Idx> {
+    type Item = (Vec<Idx>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(value) = self.root.take() {
+            return Some((vec!(), value));
+        }
+
+        while let Some((key, node, depth)) = self.stack.pop() {
+            self.stack.extend(node.nodes.iter().rev().map(|(key, node)| (key, node.as_ref(), depth + 1)));
+
+            self.path.truncate(depth);
+            self.path.push(key.clone());
+
+            if let Some(value) = node.value.as_deref() {
+                return Some((self.path.clone(), value));
+            }
+        }
+
+        return None;
+    }
+}
+
+
+// Cloning only copies the root pointer, so it is O(1) regardless of the size of the tree
+impl<T, Idx: PartialEq + Clone> Clone for PersistentTreeMap<T, Idx> {
+    fn clone(&self) -> Self {
+        PersistentTreeMap {
+            head: Arc::clone(&self.head),
+            len: self.len
+        }
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> Default for PersistentTreeMap<T, Idx> {
+    fn default() -> Self {
+        PersistentTreeMap::new()
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> FromIterator<(Vec<Idx>, T)> for PersistentTreeMap<T, Idx> {
+    fn from_iter<I: IntoIterator<Item = (Vec<Idx>, T)>>(iter: I) -> Self {
+        let mut map = PersistentTreeMap::new();
+        for (index, value) in iter {
+            map = map.insert(&index, value);
+        }
+
+        return map;
+    }
+}
+
+
+// Formats the tree as a map from full index to value
+impl<T: Debug, Idx: PartialEq + Clone + Debug> Debug for PersistentTreeMap<T, Idx> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.iter())
+            .finish()
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    Test: Insert and Find
+
+    Summary:
+        Checks that inserting creates a new version and leaves the old one unchanged
+     */
+    #[test]
+    fn insert_find() {
+        let v0: PersistentTreeMap<i32, &str> = PersistentTreeMap::new();
+        let v1 = v0.insert(vec!["a", "b"].as_slice(), 1);
+        let v2 = v1.insert(vec!["a", "c"].as_slice(), 2);
+        let v3 = v2.insert(vec!["a", "b"].as_slice(), 3);
+
+        assert_eq!(v0.find(vec!["a", "b"].as_slice()), None);
+        assert_eq!(v1.find(vec!["a", "b"].as_slice()), Some(&1));
+        assert_eq!(v1.find(vec!["a", "c"].as_slice()), None);
+        assert_eq!(v2.find(vec!["a", "c"].as_slice()), Some(&2));
+        assert_eq!(v3.find(vec!["a", "b"].as_slice()), Some(&3));
+        assert_eq!(v2.find(vec!["a", "b"].as_slice()), Some(&1));
+        assert_eq!(v3.find(vec!["a"].as_slice()), None);
+
+        assert_eq!((v0.len(), v1.len(), v2.len(), v3.len()), (0, 1, 2, 2));
+    }
+
+    /*
+    Test: Empty Index
+
+    Summary:
+        Checks that the empty index is stored in the root of a new version, as in the TreeMap
+     */
+    #[test]
+    fn empty_index() {
+        let v1: PersistentTreeMap<i32, &str> = PersistentTreeMap::new().insert(vec!["a"].as_slice(), 1);
+        let v2 = v1.insert(vec![].as_slice(), 2);
+        let v3 = v2.insert(vec![].as_slice(), 3);
+
+        assert_eq!(v1.find(vec![].as_slice()), None);
+        assert_eq!(v2.find(vec![].as_slice()), Some(&2));
+        assert_eq!(v3.find(vec![].as_slice()), Some(&3));
+        assert_eq!((v1.len(), v2.len(), v3.len()), (1, 2, 2));
+        assert_eq!(format!("{:?}", v3), r#"{[]: 3, ["a"]: 1}"#);
+
+        // Removing the root value keeps the rest of the tree
+        let v4 = v3.remove(vec![].as_slice());
+        assert_eq!(v4.find(vec![].as_slice()), None);
+        assert_eq!(v4.find(vec!["a"].as_slice()), Some(&1));
+        assert_eq!(v4.len(), 1);
+        assert!(v4.remove(vec![].as_slice()).ptr_eq(&v4));
+
+        // A root value with nothing below it is pruned back to an empty tree
+        let v5 = PersistentTreeMap::<i32, &str>::new().insert(vec![].as_slice(), 5).remove(vec![].as_slice());
+        assert!(v5.is_empty());
+        assert_eq!(v5.iter().count(), 0);
+    }
+
+    /*
+    Test: Remove
+
+    Summary:
+        Checks that removing creates a new version, prunes empty nodes and leaves the old one unchanged
+     */
+    #[test]
+    fn remove() {
+        let v1: PersistentTreeMap<i32, &str> = vec!(
+            (vec!["a", "b", "c"], 1),
+            (vec!["a", "d"], 2),
+            (vec!["e"], 3)
+        ).into_iter().collect();
+
+        let v2 = v1.remove(vec!["a", "b", "c"].as_slice());
+        assert_eq!(v2.find(vec!["a", "b", "c"].as_slice()), None);
+        assert_eq!(v1.find(vec!["a", "b", "c"].as_slice()), Some(&1));
+        assert_eq!(v2.len(), 2);
+        assert_eq!(format!("{:?}", v2), r#"{["a", "d"]: 2, ["e"]: 3}"#);
+
+        // Removing a missing value shares the whole tree
+        assert!(v2.remove(vec!["a", "b"].as_slice()).ptr_eq(&v2));
+
+        let v3 = v2.remove(vec!["a", "d"].as_slice()).remove(vec!["e"].as_slice());
+        assert!(v3.is_empty());
+        assert_eq!(v3.iter().count(), 0);
+        assert_eq!(v1.len(), 3);
+    }
+
+    /*
+    Test: Structural Sharing
+
+    Summary:
+        Checks that subtrees untouched by an insert are shared between versions
+     */
+    #[test]
+    fn structural_sharing() {
+        let v1: PersistentTreeMap<i32, &str> = vec!(
+            (vec!["a", "b"], 1),
+            (vec!["c", "d"], 2)
+        ).into_iter().collect();
+        let v2 = v1.insert(vec!["a", "e"].as_slice(), 3);
+
+        // The "c" subtree is untouched and the "a" subtree is copied
+        assert!(Arc::ptr_eq(&v1.head.nodes[1].1, &v2.head.nodes[1].1));
+        assert!(!Arc::ptr_eq(&v1.head.nodes[0].1, &v2.head.nodes[0].1));
+
+        // Snapshots share the root
+        let snapshot = v2.clone();
+        assert!(snapshot.ptr_eq(&v2));
+    }
+
+    /*
+    Test: Snapshot Across Threads
+
+    Summary:
+        Checks that a reader thread keeps a consistent snapshot while a writer publishes new versions
+     */
+    #[test]
+    fn snapshot_threads() {
+        use std::sync::RwLock;
+
+        let current = Arc::new(RwLock::new(PersistentTreeMap::<usize, usize>::new()));
+
+        let writer = {
+            let current = Arc::clone(&current);
+            std::thread::spawn(move || {
+                for i in 0..100 {
+                    let next = current.read().unwrap().insert(vec![i % 10, i].as_slice(), i);
+                    *current.write().unwrap() = next;
+                }
+            })
+        };
+
+        let reader = {
+            let current = Arc::clone(&current);
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    let snapshot = current.read().unwrap().clone();
+                    assert_eq!(snapshot.iter().count(), snapshot.len());
+                }
+            })
+        };
+
+        writer.join().unwrap();
+        reader.join().unwrap();
+
+        let last = current.read().unwrap().clone();
+        assert_eq!(last.len(), 100);
+        assert_eq!(last.find(vec![3, 43].as_slice()), Some(&43));
+    }
+}
@@ -0,0 +1,434 @@
+/*
+Summary:
+    The radix_tree_map module contains a path-compressed variant of the TreeMap
+    Instead of one node per key, a node stores the run of keys that leads to it from its parent
+    Chains of nodes that have a single child and no value are therefore collapsed into one node
+
+    A node is split when an insert diverges part way along its run, and merged back
+    into its only child when a remove leaves it without a value
+
+Tests:
+    - Insert and find
+    - Split
+    - Remove and merge
+    - Empty index
+*/
+use std::fmt::Debug;
+
+
+// RadixNode<T, Idx>
+// T - The value to store
+// Idx - The index type. Must be equatable and clonable
+//
+// A node of a RadixTreeMap
+// Every sub-node has a non-empty run, and no two sub-nodes start with the same key
+struct RadixNode<T, Idx> {
+    // The keys that lead from the parent to this node
+    // This is empty for the root only
+    run: Vec<Idx>,
+
+    // The value stored by the node
+    // Nodes without a value always have at least two sub-nodes, except for the root
+    value: Option<T>,
+
+    // The owned sub-nodes underneath this node
+    nodes: Vec<RadixNode<T, Idx>>
+}
+
+
+// RadixTreeMap<T, Idx>
+// T - The value type to store
+// Idx - The index type. Must be equatable and clonable
+//
+// A tree map with the same insert and find semantics as the TreeMap
+// The root holds no keys and holds the value of the empty index
+pub struct RadixTreeMap<T, Idx: PartialEq + Clone> {
+    // The top-most node of the tree
+    head: RadixNode<T, Idx>,
+
+    // The number of values stored in the tree
+    len: usize
+}
+
+
+// Iter<'a, T, Idx>
+// Iterates over the (index, value) pairs of a RadixTreeMap in depth-first order
+pub struct Iter<'a, T, Idx: PartialEq + Clone> {
+    // The nodes still to visit, each with the length of the path to its parent
+    stack: Vec<(&'a RadixNode<T, Idx>, usize)>,
+
+    // The keys that lead to the node most recently visited
+    path: Vec<Idx>
+}
+
+
+
+
+/*
+Implementations
+*/
+
+
+impl<T, Idx: PartialEq + Clone> RadixNode<T, Idx> {
+    fn new(run: Vec<Idx>, value: Option<T>) -> Self {
+        RadixNode {
+            run,
+            value,
+            nodes: vec!()
+        }
+    }
+
+    fn insert(&mut self, index: &[Idx], value: T) -> Option<T> {
+        if index.len() == 0 {
+            return self.value.replace(value);
+        }
+
+        let position = match self.nodes.iter().position(|n| n.run[0] == index[0]) {
+            Some(i) => i,
+            None => {
+                // No sub-node shares a key, so the whole remaining index becomes one node
+                self.nodes.push(RadixNode::new(index.to_vec(), Some(value)));
+                return None;
+            }
+        };
+
+        let node = &mut self.nodes[position];
+        let common = node.run.iter()
+            .zip(index)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        // The index diverges part way along the run, so split the node at that point
+        if common < node.run.len() {
+            node.split(common);
+        }
+
+        return node.insert(&index[common..], value);
+    }
+
+    // Splits this node so that it keeps the first at keys of its run
+    // Everything else moves into a new, single sub-node
+    fn split(&mut self, at: usize) {
+        let tail = RadixNode {
+            run: self.run.split_off(at),
+            value: self.value.take(),
+            nodes: std::mem::take(&mut self.nodes)
+        };
+
+        self.nodes.push(tail);
+    }
+
+    fn find(&self, index: &[Idx]) -> Option<&T> {
+        return self.find_node(index)?.value.as_ref();
+    }
+
+    fn find_mut(&mut self, index: &[Idx]) -> Option<&mut T> {
+        if index.len() == 0 {
+            return self.value.as_mut();
+        }
+
+        let node = self.nodes.iter_mut().find(|n| n.run[0] == index[0])?;
+        if !index.starts_with(&node.run) { return None; }
+
+        return node.find_mut(&index[node.run.len()..]);
+    }
+
+    fn remove(&mut self, index: &[Idx]) -> Option<T> {
+        if index.len() == 0 {
+            return self.value.take();
+        }
+
+        let position = self.nodes.iter().position(|n| n.run[0] == index[0])?;
+        let node = &mut self.nodes[position];
+        if !index.starts_with(&node.run) { return None; }
+
+        let old = node.remove(&index[node.run.len()..])?;
+
+        // Restore the invariant that valueless nodes have at least two sub-nodes
+        if node.value.is_none() {
+            match node.nodes.len() {
+                0 => { self.nodes.remove(position); },
+                1 => node.merge(),
+                _ => { }
+            }
+        }
+
+        return Some(old);
+    }
+
+    // Merges this node with its only sub-node by appending the sub-node's run to this one
+    fn merge(&mut self) {
+        let child = self.nodes.pop().unwrap();
+
+        self.run.extend(child.run);
+        self.value = child.value;
+        self.nodes = child.nodes;
+    }
+
+    fn find_node(&self, index: &[Idx]) -> Option<&RadixNode<T, Idx>> {
+        if index.len() == 0 {
+            return Some(self);
+        }
+
+        let node = self.nodes.iter().find(|n| n.run[0] == index[0])?;
+        if !index.starts_with(&node.run) { return None; }
+
+        return node.find_node(&index[node.run.len()..]);
+    }
+
+    fn count(&self) -> usize {
+        return 1 + self.nodes.iter().map(|n| n.count()).sum::<usize>();
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> RadixTreeMap<T, Idx> {
+    pub fn new() -> Self {
+        RadixTreeMap {
+            head: RadixNode::new(vec!(), None),
+            len: 0
+        }
+    }
+
+    // Inserts a value into the tree
+    // This takes a slice of keys that make up the whole index
+    // If the value already exists it is ovewritten and the old value is returned
+    // The empty index is stored in the root node
+    pub fn insert(&mut self, index: &[Idx], value: T) -> Option<T> {
+        let old = self.head.insert(index, value);
+        if old.is_none() { self.len += 1; }
+
+        return old;
+    }
+
+    // Finds and returns a value from the tree
+    // If the value exists then a reference to it is returned
+    // Otherwise None is returned
+    pub fn find(&self, index: &[Idx]) -> Option<&T> {
+        return self.head.find(index);
+    }
+
+    // Mutable counterpart of find
+    pub fn find_mut(&mut self, index: &[Idx]) -> Option<&mut T> {
+        return self.head.find_mut(index);
+    }
+
+    // Removes a value from the tree
+    // If the value exists it is returned
+    // Nodes left without a value are merged with their only sub-node or removed
+    pub fn remove(&mut self, index: &[Idx]) -> Option<T> {
+        let old = self.head.remove(index);
+        if old.is_some() { self.len -= 1; }
+
+        return old;
+    }
+
+    // Returns the number of values stored in the tree
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    // Returns true if the tree holds no values
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    // Returns the number of nodes in the tree, not counting the root
+    pub fn node_count(&self) -> usize {
+        return self.head.count() - 1;
+    }
+
+    // Removes every value from the tree
+    pub fn clear(&mut self) {
+        self.head = RadixNode::new(vec!(), None);
+        self.len = 0;
+    }
+
+    // Returns a depth-first iterator over every (index, value) pair
+    // The value of the empty index comes first
+    pub fn iter(&self) -> Iter<'_, T, Idx> {
+        return Iter {
+            stack: vec!((&self.head, 0)),
+            path: vec!()
+        };
+    }
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone> Iterator for Iter<'a, T, Idx> {
+    type Item = (Vec<Idx>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, depth)) = self.stack.pop() {
+            self.path.truncate(depth);
+            self.path.extend(node.run.iter().cloned());
+
+            let depth = self.path.len();
+            self.stack.extend(node.nodes.iter().rev().map(|n| (n, depth)));
+
+            if let Some(value) = node.value.as_ref() {
+                return Some((self.path.clone(), value));
+            }
+        }
+
+        return None;
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> Default for RadixTreeMap<T, Idx> {
+    fn default() -> Self {
+        RadixTreeMap::new()
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> FromIterator<(Vec<Idx>, T)> for RadixTreeMap<T, Idx> {
+    fn from_iter<I: IntoIterator<Item = (Vec<Idx>, T)>>(iter: I) -> Self {
+        let mut map = RadixTreeMap::new();
+        map.extend(iter);
+
+        return map;
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone> Extend<(Vec<Idx>, T)> for RadixTreeMap<T, Idx> {
+    fn extend<I: IntoIterator<Item = (Vec<Idx>, T)>>(&mut self, iter: I) {
+        for (index, value) in iter {
+            self.insert(&index, value);
+        }
+    }
+}
+
+
+// Formats the tree as a map from full index to value
+impl<T: Debug, Idx: PartialEq + Clone + Debug> Debug for RadixTreeMap<T, Idx> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.iter())
+            .finish()
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    Test: Insert and Find
+
+    Summary:
+        Checks that values can be found at, inside and past compressed runs
+     */
+    #[test]
+    fn insert_find() {
+        let mut map: RadixTreeMap<i32, &str> = RadixTreeMap::new();
+
+        assert_eq!(map.insert(vec!["a", "b", "c", "d"].as_slice(), 1), None);
+        assert_eq!(map.node_count(), 1);
+
+        assert_eq!(map.find(vec!["a", "b", "c", "d"].as_slice()), Some(&1));
+        assert_eq!(map.find(vec!["a", "b"].as_slice()), None);
+        assert_eq!(map.find(vec!["a", "b", "x", "d"].as_slice()), None);
+        assert_eq!(map.find(vec!["a", "b", "c", "d", "e"].as_slice()), None);
+        assert_eq!(map.find(vec![].as_slice()), None);
+
+        assert_eq!(map.insert(vec!["a", "b", "c", "d"].as_slice(), 2), Some(1));
+        *map.find_mut(vec!["a", "b", "c", "d"].as_slice()).unwrap() += 10;
+        assert_eq!(map.find(vec!["a", "b", "c", "d"].as_slice()), Some(&12));
+        assert_eq!(map.len(), 1);
+    }
+
+    /*
+    Test: Split
+
+    Summary:
+        Checks that inserting part way along a run splits the node
+     */
+    #[test]
+    fn split() {
+        let mut map: RadixTreeMap<i32, &str> = RadixTreeMap::new();
+        map.insert(vec!["a", "b", "c", "d"].as_slice(), 1);
+
+        // Diverging after "a", "b" splits into "a", "b" -> { "c", "d" ; "x" }
+        map.insert(vec!["a", "b", "x"].as_slice(), 2);
+        assert_eq!(map.node_count(), 3);
+
+        // Ending inside the "c", "d" run splits it again
+        map.insert(vec!["a", "b", "c"].as_slice(), 3);
+        assert_eq!(map.node_count(), 4);
+
+        // Ending exactly on an existing split adds no nodes
+        map.insert(vec!["a", "b"].as_slice(), 4);
+        assert_eq!(map.node_count(), 4);
+
+        assert_eq!(map.find(vec!["a", "b", "c", "d"].as_slice()), Some(&1));
+        assert_eq!(map.find(vec!["a", "b", "x"].as_slice()), Some(&2));
+        assert_eq!(map.find(vec!["a", "b", "c"].as_slice()), Some(&3));
+        assert_eq!(map.find(vec!["a", "b"].as_slice()), Some(&4));
+        assert_eq!(map.find(vec!["a"].as_slice()), None);
+        assert_eq!(format!("{:?}", map), r#"{["a", "b"]: 4, ["a", "b", "c"]: 3, ["a", "b", "c", "d"]: 1, ["a", "b", "x"]: 2}"#);
+    }
+
+    /*
+    Test: Remove and Merge
+
+    Summary:
+        Checks that removing values merges runs back together and prunes empty nodes
+     */
+    #[test]
+    fn remove_merge() {
+        let mut map: RadixTreeMap<i32, &str> = vec!(
+            (vec!["a", "b", "c", "d"], 1),
+            (vec!["a", "b", "x"], 2),
+            (vec!["a", "b", "c"], 3)
+        ).into_iter().collect();
+        assert_eq!(map.node_count(), 4);
+
+        assert_eq!(map.remove(vec!["a", "b"].as_slice()), None);
+        assert_eq!(map.remove(vec!["a", "b", "c", "d", "e"].as_slice()), None);
+
+        // "a", "b", "c" is merged with its only sub-node "d"
+        assert_eq!(map.remove(vec!["a", "b", "c"].as_slice()), Some(3));
+        assert_eq!(map.node_count(), 3);
+
+        // "x" is pruned, and "a", "b" is merged with "c", "d"
+        assert_eq!(map.remove(vec!["a", "b", "x"].as_slice()), Some(2));
+        assert_eq!(map.node_count(), 1);
+        assert_eq!(map.find(vec!["a", "b", "c", "d"].as_slice()), Some(&1));
+
+        assert_eq!(map.remove(vec!["a", "b", "c", "d"].as_slice()), Some(1));
+        assert_eq!(map.node_count(), 0);
+        assert!(map.is_empty());
+    }
+    /*
+    Test: Empty Index
+
+    Summary:
+        Checks that the empty index is stored in the root, as in the TreeMap
+     */
+    #[test]
+    fn empty_index() {
+        let mut map: RadixTreeMap<i32, &str> = RadixTreeMap::new();
+        map.insert(vec!["a", "b"].as_slice(), 1);
+
+        assert_eq!(map.insert(vec![].as_slice(), 2), None);
+        assert_eq!(map.insert(vec![].as_slice(), 3), Some(2));
+        *map.find_mut(vec![].as_slice()).unwrap() += 10;
+        assert_eq!(map.find(vec![].as_slice()), Some(&13));
+        assert_eq!(map.len(), 2);
+
+        // The root is never merged or pruned, so the rest of the tree is unchanged
+        assert_eq!(map.node_count(), 1);
+        assert_eq!(format!("{:?}", map), r#"{[]: 13, ["a", "b"]: 1}"#);
+
+        assert_eq!(map.remove(vec![].as_slice()), Some(13));
+        assert_eq!(map.remove(vec![].as_slice()), None);
+        assert_eq!(map.find(vec!["a", "b"].as_slice()), Some(&1));
+        assert_eq!(map.len(), 1);
+    }
+}
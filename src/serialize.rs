@@ -0,0 +1,575 @@
+/*
+Summary:
+    The serialize module implements serde's Serialize and Deserialize for TreeMap, StaticTreePlanner and StaticTree
+    It is only compiled with the serde feature
+
+    A tree is written in one of two forms
+    When every key serializes as a string the tree is written as nested nodes, which reads naturally as JSON or YAML
+        {"value": 0, "children": {"a": {"children": {"b": {"value": 1}}}}}
+    Each node is a map with an optional "value" and an optional "children" map from key to node
+    The top-most node holds the value of the empty index
+
+    Any other key type falls back to a list of (index, value) pairs, as formats such as JSON only allow string map keys
+        [[[1, 2], "a"], [[1], "b"]]
+
+    Either form is accepted when reading a tree back, so deserializing needs a self-describing format
+    A StaticTree is written as its logical contents and is rebuilt through a StaticTreePlanner when read back
+
+Tests:
+    - Nested
+    - Paths
+    - Planner
+    - Static tree
+*/
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::ser::{ Serialize, Serializer, SerializeStruct, SerializeMap, Impossible };
+use serde::de::{ self, Deserialize, Deserializer, DeserializeSeed, Visitor, MapAccess, SeqAccess };
+
+use crate::tree_map::{ TreeMap, TreeNode };
+use crate::child_storage::{ ChildStorage, Children };
+use crate::static_tree_planner::StaticTreePlanner;
+use crate::static_tree::{ self, StaticTree };
+use crate::counted_tree_map::CountedTreeNode;
+
+
+// The field names of a node in the nested form
+const VALUE: &str = "value";
+const CHILDREN: &str = "children";
+const FIELDS: &[&str] = &[VALUE, CHILDREN];
+
+
+// An (index, value) pair collected from a tree
+type Entry<'a, T, Idx> = (Vec<Idx>, &'a T);
+
+// An (index, value) pair read back from either form
+type OwnedEntry<T, Idx> = (Vec<Idx>, T);
+
+
+
+
+/*
+Standard Structures
+*/
+impl<T: Serialize, Idx: PartialEq + Clone + Serialize, S: ChildStorage<T, Idx>> Serialize for TreeMap<T, Idx, S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        return serialize_tree(self.root(), serializer);
+    }
+}
+impl<'de, T: Deserialize<'de>, Idx: PartialEq + Clone + Deserialize<'de>, S: ChildStorage<T, Idx>> Deserialize<'de> for TreeMap<T, Idx, S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        return Ok(deserialize_entries(deserializer)?.into_iter().collect());
+    }
+}
+
+
+impl<T: Serialize, Idx: PartialEq + Clone + Serialize> Serialize for StaticTreePlanner<T, Idx> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return serialize_tree(self.root(), serializer);
+    }
+}
+impl<'de, T: Deserialize<'de>, Idx: PartialEq + Clone + Deserialize<'de>> Deserialize<'de> for StaticTreePlanner<T, Idx> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        return Ok(deserialize_entries(deserializer)?.into_iter().collect());
+    }
+}
+
+
+// A StaticTree is written in the same form as the StaticTreePlanner it was compiled from
+impl<T: Serialize, Idx: PartialEq + Clone + Serialize> Serialize for StaticTree<T, Idx> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return serialize_tree(StaticNode { tree: self, node: self.node(0) }, serializer);
+    }
+}
+// Reads a tree in either form and compiles it with a StaticTreePlanner
+impl<'de, T: Deserialize<'de>, Idx: PartialEq + Clone + Deserialize<'de>> Deserialize<'de> for StaticTree<T, Idx> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let plan: StaticTreePlanner<T, Idx> = StaticTreePlanner::deserialize(deserializer)?;
+
+        return Ok(plan.compile());
+    }
+}
+
+
+
+
+/*
+Serializing
+*/
+
+
+// TreeView<'a>
+//
+// A node of a tree being serialized
+// The nested form is written by walking the nodes directly, so no index is built unless the list form is needed
+trait TreeView<'a>: Copy {
+    type Key: 'a;
+    type Value: 'a;
+
+    fn value(self) -> Option<&'a Self::Value>;
+
+    // The sub-nodes of this node along with their keys, in the order they are written
+    fn branches(self) -> impl Iterator<Item = (&'a Self::Key, Self)>;
+}
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> TreeView<'a> for &'a TreeNode<T, Idx, S> {
+    type Key = Idx;
+    type Value = T;
+
+    fn value(self) -> Option<&'a T> {
+        return self.value.as_ref();
+    }
+
+    fn branches(self) -> impl Iterator<Item = (&'a Idx, Self)> {
+        return self.nodes.iter();
+    }
+}
+
+impl<'a, T, Idx: PartialEq + Clone> TreeView<'a> for &'a CountedTreeNode<T, Idx> {
+    type Key = Idx;
+    type Value = T;
+
+    fn value(self) -> Option<&'a T> {
+        return self.value.as_ref();
+    }
+
+    fn branches(self) -> impl Iterator<Item = (&'a Idx, Self)> {
+        return self.nodes.iter().map(|node| (node.key.as_ref().expect("Only the root node has no key"), node));
+    }
+}
+
+
+// StaticNode<'a, T, Idx>
+//
+// A node of a StaticTree, which needs the tree to find its branches
+struct StaticNode<'a, T, Idx: PartialEq> {
+    tree: &'a StaticTree<T, Idx>,
+    node: &'a static_tree::TreeNode<T, Idx>
+}
+
+impl<'a, T, Idx: PartialEq> Clone for StaticNode<'a, T, Idx> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<'a, T, Idx: PartialEq> Copy for StaticNode<'a, T, Idx> { }
+
+impl<'a, T, Idx: PartialEq + Clone> TreeView<'a> for StaticNode<'a, T, Idx> {
+    type Key = Idx;
+    type Value = T;
+
+    fn value(self) -> Option<&'a T> {
+        return self.node.value.as_ref();
+    }
+
+    fn branches(self) -> impl Iterator<Item = (&'a Idx, Self)> {
+        let tree = self.tree;
+
+        return tree.branches(self.node)
+            .map(move |node| (node.key.as_ref().expect("Only the root node has no key"), StaticNode { tree, node }));
+    }
+}
+
+
+// Writes a tree in the nested form if every key is a string, otherwise as a list of pairs
+fn serialize_tree<'a, N: TreeView<'a>, S: Serializer>(root: N, serializer: S) -> Result<S::Ok, S::Error>
+where N::Key: Serialize + Clone, N::Value: Serialize {
+    if string_keys(root) {
+        return Node(root).serialize(serializer);
+    }
+
+    // Only the list form needs the full index of every value
+    let mut entries = vec!();
+    collect_entries(root, &mut vec!(), &mut entries);
+
+    return serializer.collect_seq(entries);
+}
+
+// Checks that every key below the node serializes as a string
+fn string_keys<'a, N: TreeView<'a>>(node: N) -> bool where N::Key: Serialize {
+    return node.branches().all(|(key, branch)| key.serialize(StringProbe).is_ok() && string_keys(branch));
+}
+
+// Collects every (index, value) pair below the node in depth-first order, with each value before the values below it
+// path holds the index of the node and is restored before returning
+fn collect_entries<'a, N: TreeView<'a>>(node: N, path: &mut Vec<N::Key>, out: &mut Vec<Entry<'a, N::Value, N::Key>>) where N::Key: Clone {
+    if let Some(value) = node.value() {
+        out.push((path.clone(), value));
+    }
+
+    for (key, branch) in node.branches() {
+        path.push(key.clone());
+        collect_entries(branch, path, out);
+        path.pop();
+    }
+}
+
+
+// Node<N>
+//
+// A node in the nested form, written as its value and a map of its children
+struct Node<N>(N);
+
+// Branches<N>
+//
+// The children of a node in the nested form, written as a map from key to node
+struct Branches<N>(N);
+
+impl<'a, N: TreeView<'a>> Serialize for Node<N> where N::Key: Serialize, N::Value: Serialize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = self.0.value();
+        let has_children = self.0.branches().next().is_some();
+
+        let field_count = value.is_some() as usize + has_children as usize;
+        let mut node = serializer.serialize_struct("Node", field_count)?;
+
+        match value {
+            Some(value) => node.serialize_field(VALUE, value)?,
+            None => node.skip_field(VALUE)?
+        };
+
+        if has_children {
+            node.serialize_field(CHILDREN, &Branches(self.0))?;
+        } else {
+            node.skip_field(CHILDREN)?;
+        }
+
+        return node.end();
+    }
+}
+
+impl<'a, N: TreeView<'a>> Serialize for Branches<N> where N::Key: Serialize, N::Value: Serialize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+
+        for (key, branch) in self.0.branches() {
+            map.serialize_entry(key, &Node(branch))?;
+        }
+
+        return map.end();
+    }
+}
+
+
+// StringProbe
+//
+// A Serializer that succeeds only for values that are written as strings
+// This decides whether the keys of a tree can be used as map keys in the nested form
+// Unit enum variants and chars are written as strings by self-describing formats so they are accepted too
+struct StringProbe;
+
+// Rejects every serialize method it is given
+macro_rules! reject {
+    ($($method:ident($($arg:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ok, fmt::Error> {
+                return Err(fmt::Error);
+            }
+        )*
+    };
+}
+
+impl Serializer for StringProbe {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    type SerializeSeq = Impossible<(), fmt::Error>;
+    type SerializeTuple = Impossible<(), fmt::Error>;
+    type SerializeTupleStruct = Impossible<(), fmt::Error>;
+    type SerializeTupleVariant = Impossible<(), fmt::Error>;
+    type SerializeMap = Impossible<(), fmt::Error>;
+    type SerializeStruct = Impossible<(), fmt::Error>;
+    type SerializeStructVariant = Impossible<(), fmt::Error>;
+
+    fn serialize_str(self, _: &str) -> Result<(), fmt::Error> {
+        return Ok(());
+    }
+
+    fn serialize_char(self, _: char) -> Result<(), fmt::Error> {
+        return Ok(());
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> Result<(), fmt::Error> {
+        return Ok(());
+    }
+
+    // Wrappers are transparent, so a newtype around a String is still a string
+    fn serialize_newtype_struct<V: ?Sized + Serialize>(self, _: &'static str, value: &V) -> Result<(), fmt::Error> {
+        return value.serialize(self);
+    }
+
+    fn serialize_some<V: ?Sized + Serialize>(self, _: &V) -> Result<(), fmt::Error> {
+        return Err(fmt::Error);
+    }
+
+    fn serialize_newtype_variant<V: ?Sized + Serialize>(self, _: &'static str, _: u32, _: &'static str, _: &V) -> Result<(), fmt::Error> {
+        return Err(fmt::Error);
+    }
+
+    reject! {
+        serialize_bool(bool) -> ();
+        serialize_i8(i8) -> ();
+        serialize_i16(i16) -> ();
+        serialize_i32(i32) -> ();
+        serialize_i64(i64) -> ();
+        serialize_u8(u8) -> ();
+        serialize_u16(u16) -> ();
+        serialize_u32(u32) -> ();
+        serialize_u64(u64) -> ();
+        serialize_f32(f32) -> ();
+        serialize_f64(f64) -> ();
+        serialize_bytes(&[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(&'static str) -> ();
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+
+
+
+/*
+Deserializing
+*/
+
+
+// Reads the entries of a tree in either form
+// The caller collects them into the tree being deserialized
+fn deserialize_entries<'de, T: Deserialize<'de>, Idx: Clone + Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<OwnedEntry<T, Idx>>, D::Error> {
+    return deserializer.deserialize_any(EntriesVisitor(PhantomData));
+}
+
+// Reads the fields of a node in the nested form
+// path holds the index of the node and is restored before returning
+fn visit_node<'de, T: Deserialize<'de>, Idx: Clone + Deserialize<'de>, A: MapAccess<'de>>(mut map: A, path: &mut Vec<Idx>, out: &mut Vec<OwnedEntry<T, Idx>>) -> Result<(), A::Error> {
+    while let Some(field) = map.next_key::<String>()? {
+        match field.as_str() {
+            VALUE => out.push((path.clone(), map.next_value()?)),
+            CHILDREN => map.next_value_seed(BranchesSeed { path, out })?,
+            _ => { return Err(de::Error::unknown_field(&field, FIELDS)); }
+        };
+    }
+
+    return Ok(());
+}
+
+
+// Accepts a tree in the nested form or as a list of pairs
+struct EntriesVisitor<T, Idx>(PhantomData<(T, Idx)>);
+
+// Reads a node in the nested form into out
+struct NodeSeed<'p, T, Idx> {
+    path: &'p mut Vec<Idx>,
+    out: &'p mut Vec<OwnedEntry<T, Idx>>
+}
+
+// Reads the children map of a node in the nested form into out
+struct BranchesSeed<'p, T, Idx> {
+    path: &'p mut Vec<Idx>,
+    out: &'p mut Vec<OwnedEntry<T, Idx>>
+}
+
+impl<'de, T: Deserialize<'de>, Idx: Clone + Deserialize<'de>> Visitor<'de> for EntriesVisitor<T, Idx> {
+    type Value = Vec<OwnedEntry<T, Idx>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a tree node or a list of (index, value) pairs")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let mut out = vec!();
+        visit_node(map, &mut vec!(), &mut out)?;
+
+        return Ok(out);
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut out = vec!();
+        while let Some(entry) = seq.next_element()? {
+            out.push(entry);
+        }
+
+        return Ok(out);
+    }
+}
+
+impl<'de, 'p, T: Deserialize<'de>, Idx: Clone + Deserialize<'de>> DeserializeSeed<'de> for NodeSeed<'p, T, Idx> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        return deserializer.deserialize_map(self);
+    }
+}
+impl<'de, 'p, T: Deserialize<'de>, Idx: Clone + Deserialize<'de>> Visitor<'de> for NodeSeed<'p, T, Idx> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a tree node")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<(), A::Error> {
+        return visit_node(map, self.path, self.out);
+    }
+}
+
+impl<'de, 'p, T: Deserialize<'de>, Idx: Clone + Deserialize<'de>> DeserializeSeed<'de> for BranchesSeed<'p, T, Idx> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        return deserializer.deserialize_map(self);
+    }
+}
+impl<'de, 'p, T: Deserialize<'de>, Idx: Clone + Deserialize<'de>> Visitor<'de> for BranchesSeed<'p, T, Idx> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map of child nodes")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<Idx>()? {
+            self.path.push(key);
+            map.next_value_seed(NodeSeed { path: &mut *self.path, out: &mut *self.out })?;
+            self.path.pop();
+        }
+
+        return Ok(());
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::child_storage::Hashed;
+    use serde_json::json;
+
+    /*
+    Test: Nested
+
+    Summary:
+        Checks that trees with string keys are written as nested nodes and read back unchanged
+     */
+    #[test]
+    fn nested() {
+        let mut map: TreeMap<i32, String> = TreeMap::new();
+        map.insert(&[], 0);
+        map.insert_path("a/b", '/', 1);
+        map.insert_path("a/c", '/', 2);
+        map.insert_path("d", '/', 3);
+
+        let value = serde_json::to_value(&map).unwrap();
+        assert_eq!(value, json!({
+            "value": 0,
+            "children": {
+                "a": { "children": { "b": { "value": 1 }, "c": { "value": 2 } } },
+                "d": { "value": 3 }
+            }
+        }));
+
+        let back: TreeMap<i32, String, Hashed> = serde_json::from_value(value).unwrap();
+        assert_eq!(back.len(), 4);
+        assert_eq!(back.find_path("a/c", '/'), Some(&2));
+        assert_eq!(back.find::<str, [&str; 0]>([]), Some(&0));
+
+        let empty: TreeMap<i32, String> = TreeMap::new();
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+        assert!(serde_json::from_str::<TreeMap<i32, String>>("{}").unwrap().is_empty());
+
+        assert!(serde_json::from_str::<TreeMap<i32, String>>(r#"{"other": 1}"#).is_err());
+    }
+
+    /*
+    Test: Paths
+
+    Summary:
+        Checks that trees with other key types fall back to a list of (index, value) pairs
+     */
+    #[test]
+    fn paths() {
+        let map: TreeMap<&str, u32> = vec!(
+            (vec![1], "a"),
+            (vec![1, 2], "b"),
+            (vec![], "c")
+        ).into_iter().collect();
+
+        let text = serde_json::to_string(&map).unwrap();
+        assert_eq!(text, r#"[[[],"c"],[[1],"a"],[[1,2],"b"]]"#);
+
+        let back: TreeMap<String, u32> = serde_json::from_str(&text).unwrap();
+        assert_eq!(back.len(), 3);
+        assert_eq!(back.find(&[1, 2]), Some(&"b".to_string()));
+
+        // Either form is accepted when reading
+        let back: TreeMap<i32, String> = serde_json::from_str(r#"[[["x", "y"], 1]]"#).unwrap();
+        assert_eq!(back.find(["x", "y"]), Some(&1));
+    }
+
+    /*
+    Test: Planner
+
+    Summary:
+        Checks that a StaticTreePlanner is written in the same form as a TreeMap and read back unchanged
+     */
+    #[test]
+    fn planner() {
+        let plan = StaticTreePlanner::new()
+            .add_path("a/b", '/', 1)
+            .add_path("a", '/', 2)
+            .add_path("c", '/', 3);
+
+        let map: TreeMap<i32, String> = plan.iter_paths('/')
+            .map(|(path, value)| (crate::path::split(&path, '/'), *value))
+            .collect();
+
+        let text = serde_json::to_string(&plan).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap(), serde_json::to_value(&map).unwrap());
+
+        let back: StaticTreePlanner<i32, String> = serde_json::from_str(&text).unwrap();
+        assert_eq!(back.len(), 3);
+        assert_eq!(format!("{:?}", back), format!("{:?}", plan));
+    }
+
+    /*
+    Test: Static Tree
+
+    Summary:
+        Checks that a compiled StaticTree is written as its contents and recompiled when read back
+     */
+    #[test]
+    fn static_tree() {
+        let tree = StaticTreePlanner::<i32, u8>::new()
+            .add(&[], 0)
+            .add(&[1, 2], 1)
+            .add(&[3], 2)
+            .compile();
+
+        let text = serde_json::to_string(&tree).unwrap();
+        assert_eq!(text, "[[[],0],[[1,2],1],[[3],2]]");
+
+        let back: StaticTree<i32, u8> = serde_json::from_str(&text).unwrap();
+        assert_eq!(back.find(&[]), Some(&0));
+        assert_eq!(back.find(&[1, 2]), Some(&1));
+        assert_eq!(back.find(&[3]), Some(&2));
+        assert_eq!(back.find(&[1]), None);
+
+        let tree = StaticTreePlanner::new()
+            .add_path("a/b", '/', 1)
+            .compile();
+        let value = serde_json::to_value(&tree).unwrap();
+        assert_eq!(value, json!({ "children": { "a": { "children": { "b": { "value": 1 } } } } }));
+
+        let back: StaticTree<i32, String> = serde_json::from_value(value).unwrap();
+        assert_eq!(back.find_path("a/b", '/'), Some(&1));
+    }
+}
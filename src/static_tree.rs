@@ -7,100 +7,281 @@ Summary:
     The StaticTree must be created using the StaticTreePlanner struct
 
     Due to the continous nature of StaticTree it is recommended to store as little data as possible in the actual tree structure
+
+    The tree owns the keys and values written into its pool and drops them when it is freed
 */
 use crate::dynamic_array::DynamicArray;
+use crate::pattern::{self, PatternSegment};
+use crate::path;
 use std::alloc::Layout;
-
-#[cfg(debug_assertions)]
 use std::fmt::Debug;
+use std::marker::PhantomData;
 
 
-// TreeOffset is an i32 index into the DynamicArray structure in the StaticTree
-// It is used to specify the offset to find an item
-type TreeOffset = i32;
+// NodeIndex is the position of a node in the DynamicArray structure in the StaticTree
+// It counts nodes rather than bytes, so the size of the pool isn't limited by its width
+pub(crate) type NodeIndex = u32;
+
+// Every node must be addressable by a NodeIndex, which limits the number of nodes in a tree
+pub(crate) const MAX_NODES: usize = NodeIndex::MAX as usize;
 
 pub struct TreeNode<T, Idx: PartialEq> {
     pub key: Option<Idx>,
     pub value: Option<T>,
-    pub list_length: i32,
-    pub list_head: TreeOffset
+
+    // The branches of a node are list_length consecutive nodes starting at list_head
+    // list_head is meaningless when list_length is 0
+    pub list_length: NodeIndex,
+    pub list_head: NodeIndex
 }
 
 // pub struct TreeBranch {
-//     pub node: TreeOffset,
-//     pub next: TreeOffset
+//     pub node: NodeIndex,
+//     pub next: NodeIndex
 // }
 
+// StaticTree<T, Idx>
+// T - The value type stored in the tree
+// Idx - The index type the tree was planned with
+//
 // The readonly static tree
 // This contains a DynamicArray which contains the tree data
-pub struct StaticTree {
-    pool: DynamicArray
+// The pool is untyped memory, so T and Idx are carried by the tree to read it back as the types it was compiled with
+pub struct StaticTree<T, Idx: PartialEq> {
+    pool: DynamicArray,
+
+    // The number of nodes written into the pool
+    // Nodes are pushed in index order, so these are always the first len nodes
+    //
+    // Invariant: the root and every node in a branch list are among the first len nodes
+    // compile pushes every branch list it links before returning the tree, and read_from validates every
+    // list before the tree is created, so lookups only check indices in debug builds
+    len: usize,
+
+    _marker: PhantomData<(T, Idx)>
 }
 
 /* Implementation */
-impl StaticTree {
+impl<T, Idx: PartialEq + Clone> StaticTree<T, Idx> {
+
+    // Creates a tree with an uninitialised pool of size bytes, aligned for its nodes
+    // Every node must be written before the tree is read, so only the StaticTreePlanner and the binary loader create trees
+    pub(crate) fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, std::mem::align_of::<TreeNode<T, Idx>>())
+            .expect("StaticTree is too large to allocate");
 
-    pub fn new(size: usize) -> Self {
         StaticTree {
-            pool: DynamicArray::new(Layout::from_size_align(size, 1).unwrap())
+            pool: DynamicArray::new(layout),
+            len: 0,
+            _marker: PhantomData
         }
     }
 
+    // Creates a tree from nodes that are already linked, in pool order
+    // The nodes' allocation becomes the pool, so they aren't copied
+    pub(crate) fn from_nodes(nodes: Vec<TreeNode<T, Idx>>) -> Self {
+        let len = nodes.len();
+
+        StaticTree {
+            pool: DynamicArray::from_boxed_slice(nodes.into_boxed_slice()),
+            len,
+            _marker: PhantomData
+        }
+    }
+
+    // Returns the size in bytes of a pool that holds node_count nodes
+    // Returns None if there are more than MAX_NODES nodes or the pool is too large to allocate
+    pub(crate) fn pool_size(node_count: usize) -> Option<usize> {
+        if node_count > MAX_NODES { return None; }
+
+        return node_count.checked_mul(std::mem::size_of::<TreeNode<T, Idx>>())
+            .filter(|size| *size <= isize::MAX as usize);
+    }
+
+    // Writes a node into the next free slot of the pool
+    // If compile panics part-way the tree is dropped with only the nodes pushed so far
+    pub(crate) fn push(&mut self, node: TreeNode<T, Idx>) {
+        self.pool.write(self.len * std::mem::size_of::<TreeNode<T, Idx>>(), node);
+        self.len += 1;
+    }
+
+    // Returns the number of nodes in the tree, including the root and intermediaries without values
+    pub(crate) fn node_count(&self) -> usize {
+        return self.len;
+    }
+
+    // Returns the node at the index in the pool
+    // The index must be the root or come from a branch list, see the invariant on len
     #[inline]
-    pub fn raw(&self) -> &DynamicArray {
-        return &self.pool;
+    pub(crate) fn node(&self, index: usize) -> &TreeNode<T, Idx> {
+        debug_assert!(self.is_written(index), "StaticTree node has not been written");
+
+        // SAFETY: By the invariant on len the node has been pushed, so it is initialised
+        unsafe {
+            return self.pool.get(index * std::mem::size_of::<TreeNode<T, Idx>>());
+        }
+    }
+
+    // Mutable counterpart of node, used by compile to link nodes to their branches
+    pub(crate) fn node_mut(&mut self, index: usize) -> &mut TreeNode<T, Idx> {
+        assert!(self.is_written(index), "StaticTree node has not been written");
+
+        // SAFETY: The node has been pushed, and the &mut self borrow means nothing else is reading the pool
+        unsafe {
+            return self.pool.get_mut(index * std::mem::size_of::<TreeNode<T, Idx>>());
+        }
     }
 
     // Looks up a key in the static tree
     // Returns a reference to it if it exists
     // Otherwise returns none
     #[inline(never)]
-    pub fn find<T, Idx: 'static + PartialEq + Clone>(&self, index: &[Idx]) -> Option<&T> {
+    pub fn find(&self, index: &[Idx]) -> Option<&T> {
+        let mut current_node = self.node(0);
 
-        // State variables
-        let node_size = std::mem::size_of::<TreeNode<T, Idx>>();
-        let mut current_node: &TreeNode<T, Idx> = self.pool.get(0);
-        let mut current_offset: i32= 0;
-        let mut branch_idx = 0;
-        let mut keychain_idx = 0;
+        for key in index {
+            current_node = self.find_branch(current_node, key)?;
+        }
 
-        loop {
-            let test_node: &TreeNode<T, Idx> = self.pool.get((current_node.list_head + current_offset) as usize);
+        return current_node.value.as_ref();
+    }
 
-            // Check if node matches index
-            // If not then we continue to the next node
-            if test_node.key.as_ref() != Some(&index[keychain_idx]) {
-                if branch_idx == current_node.list_length { return None; } // Null check.
+    // Finds the deepest value along the index
+    // Returns the number of keys matched to reach the value and a reference to it
+    // If no value exists along the index then None is returned
+    // The value of the empty index is matched at depth 0
+    pub fn longest_prefix(&self, index: &[Idx]) -> Option<(usize, &T)> {
+        let mut current_node = self.node(0);
+        let mut longest = current_node.value.as_ref().map(|value| (0, value));
 
-                // Increment state variables
-                current_offset += node_size as i32;
-                branch_idx += 1;
+        for (depth, key) in index.iter().enumerate() {
+            current_node = match self.find_branch(current_node, key) {
+                Some(n) => n,
+                None => { break; }
+            };
 
-                continue; // Skip back
+            if let Some(value) = current_node.value.as_ref() {
+                longest = Some((depth + 1, value));
             }
+        }
 
-            // Otherwise, we hit the correct node
-            // Update state
-            keychain_idx += 1;
-            if keychain_idx == index.len() { // Reached end of index
-                return test_node.value.as_ref();
-            }
+        return longest;
+    }
+
+
+    // Finds every value whose index matches the pattern
+    // Each segment of the pattern is either a key, a single level wildcard or a multi level wildcard
+    //
+    // PatternSegment::Multi must only be used as the last segment
+    pub fn find_pattern(&self, pattern: &[PatternSegment<Idx>]) -> Vec<(Vec<Idx>, &T)> {
+        pattern::validate(pattern);
+
+        let mut out = vec!();
+        self.match_pattern(self.node(0), pattern, &mut vec!(), &mut out);
+
+        return out;
+    }
 
-            if test_node.list_head == -1 {
-                return None;
+    /* Helper Methods */
+    // Recursive step of find_pattern
+    // path holds the index of node and is restored before returning
+    fn match_pattern<'a>(&'a self, node: &'a TreeNode<T, Idx>, pattern: &[PatternSegment<Idx>], path: &mut Vec<Idx>, out: &mut Vec<(Vec<Idx>, &'a T)>) {
+        match pattern.first() {
+            None => {
+                if let Some(value) = node.value.as_ref() {
+                    out.push((path.clone(), value));
+                }
+            },
+            Some(PatternSegment::Key(key)) => {
+                if let Some(branch) = self.find_branch(node, key) {
+                    path.push(key.clone());
+                    self.match_pattern(branch, &pattern[1..], path, out);
+                    path.pop();
+                }
+            },
+            Some(PatternSegment::Single) => {
+                for branch in self.branches(node) {
+                    path.push(branch.key.clone().unwrap());
+                    self.match_pattern(branch, &pattern[1..], path, out);
+                    path.pop();
+                }
+            },
+            Some(PatternSegment::Multi) => {
+                // Every node below this one matches, including itself
+                if let Some(value) = node.value.as_ref() {
+                    out.push((path.clone(), value));
+                }
+
+                for branch in self.branches(node) {
+                    path.push(branch.key.clone().unwrap());
+                    self.match_pattern(branch, pattern, path, out);
+                    path.pop();
+                }
             }
+        };
+    }
+
+    // Returns an iterator over the branches of a node
+    pub(crate) fn branches<'a>(&'a self, node: &TreeNode<T, Idx>) -> impl Iterator<Item = &'a TreeNode<T, Idx>> {
+        let list_head = node.list_head as usize;
+
+        return (list_head..list_head + node.list_length as usize).map(move |index| self.node(index));
+    }
+
+    // Checks that the node at the index has been pushed
+    fn is_written(&self, index: usize) -> bool {
+        return index < self.len;
+    }
 
-            current_node = test_node;
-            branch_idx = 0;
-            current_offset = 0;
+    // Scans the branches of a node for the given key
+    // Returns None if the node has no branches or none of them match
+    #[inline]
+    fn find_branch(&self, node: &TreeNode<T, Idx>, key: &Idx) -> Option<&TreeNode<T, Idx>> {
+        return self.branches(node).find(|branch| branch.key.as_ref() == Some(key));
+    }
+}
+// Drops the key and value of every node that was pushed
+// The pool itself is freed by the DynamicArray
+impl<T, Idx: PartialEq> Drop for StaticTree<T, Idx> {
+    fn drop(&mut self) {
+        let node_size = std::mem::size_of::<TreeNode<T, Idx>>();
+
+        for i in 0..self.len {
+            // SAFETY: The first len nodes have been written and each is dropped exactly once
+            unsafe {
+                self.pool.drop_in_place::<TreeNode<T, Idx>>(i * node_size);
+            }
         }
     }
 }
+// SAFETY: The tree owns its keys and values like a Vec<TreeNode<T, Idx>> would
+// The raw pool pointer is never shared outside the tree, so moving the tree moves the nodes with it
+unsafe impl<T: Send, Idx: PartialEq + Send> Send for StaticTree<T, Idx> { }
+
+// SAFETY: A shared tree only hands out shared references to its keys and values and has no interior mutability
+unsafe impl<T: Sync, Idx: PartialEq + Sync> Sync for StaticTree<T, Idx> { }
 
 
+// String Paths
+// Trees indexed by String can be searched with delimited paths such as "a/b/c"
+impl<T> StaticTree<T, String> {
+    // Looks up a delimited path
+    // See the path module for how separators are escaped
+    pub fn find_path(&self, path: &str, separator: char) -> Option<&T> {
+        return self.find(&path::split(path, separator));
+    }
+
+    // Returns every (path, value) pair, with each index joined by the separator
+    pub fn iter_paths(&self, separator: char) -> impl Iterator<Item = (String, &T)> + '_ {
+        // A lone multi level wildcard matches every node in the tree
+        return self.find_pattern(&[PatternSegment::Multi])
+            .into_iter()
+            .map(move |(index, value)| (path::join(&index, separator), value));
+    }
+}
+
 
 // Debugging Implementations
-#[cfg(debug_assertions)]
 impl<T: Debug, Idx: PartialEq + Debug> std::fmt::Debug for TreeNode<T, Idx> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Node")
@@ -117,11 +298,22 @@ impl<T: Debug, Idx: PartialEq + Debug> std::fmt::Debug for TreeNode<T, Idx> {
 /*
 Tests:
     - Find
+    - Longest Prefix
+    - Find Pattern
+    - Empty Index
+    - Drop
+    - Compile Panic
+    - Threads
+    - Pool Bounds
 */
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::StaticTreePlanner;
+    use std::panic::AssertUnwindSafe;
+    use std::rc::Rc;
+    use std::cell::Cell;
+    use std::sync::Arc;
 
     #[test]
     fn find() {
@@ -129,16 +321,219 @@ mod tests {
         plan = plan.add(vec!["a", "b", "c"].as_slice(), 1);
         plan = plan.add(vec!["a", "b", "d"].as_slice(), 2);
         plan = plan.add(vec!["e"].as_slice(), 3);
-        let tree: StaticTree = plan.compile();
+        let tree = plan.compile();
 
 
         // Check valid lookups
-        assert_eq!(*tree.find::<i32, &str>(vec!["a", "b", "c"].as_slice()).unwrap(), 1);
-        assert_eq!(*tree.find::<i32, &str>(vec!["a", "b", "d"].as_slice()).unwrap(), 2);
-        assert_eq!(*tree.find::<i32, &str>(vec!["e"].as_slice()).unwrap(), 3);
+        assert_eq!(*tree.find(vec!["a", "b", "c"].as_slice()).unwrap(), 1);
+        assert_eq!(*tree.find(vec!["a", "b", "d"].as_slice()).unwrap(), 2);
+        assert_eq!(*tree.find(vec!["e"].as_slice()).unwrap(), 3);
 
         // Check invalid lookups
-        assert!(tree.find::<i32, &str>(vec!["g", "h"].as_slice()).is_none());
+        assert!(tree.find(vec!["g", "h"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Longest Prefix
+
+    Summary:
+        Checks that the deepest value along an index is returned even if the full index doesn't exist
+     */
+    #[test]
+    fn longest_prefix() {
+        let tree = StaticTreePlanner::new()
+            .add(vec!["10"].as_slice(), 1)
+            .add(vec!["10", "0", "1"].as_slice(), 2)
+            .add(vec!["192", "168"].as_slice(), 3)
+            .compile();
+
+        assert_eq!(tree.longest_prefix(vec!["10", "0", "1"].as_slice()), Some((3, &2)));
+        assert_eq!(tree.longest_prefix(vec!["10", "0", "2", "5"].as_slice()), Some((1, &1)));
+        assert_eq!(tree.longest_prefix(vec!["192", "168", "0"].as_slice()), Some((2, &3)));
+
+        assert!(tree.longest_prefix(vec!["192"].as_slice()).is_none());
+        assert!(tree.longest_prefix(vec!["172"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Find Pattern
+
+    Summary:
+        Checks that wildcard queries on a StaticTree match the same values as on a TreeMap
+     */
+    #[test]
+    fn find_pattern() {
+        use PatternSegment::*;
+
+        let tree = StaticTreePlanner::new()
+            .add(vec!["sport", "tennis", "player1"].as_slice(), 1)
+            .add(vec!["sport", "tennis", "player2"].as_slice(), 2)
+            .add(vec!["sport", "golf", "player1"].as_slice(), 3)
+            .add(vec!["sport"].as_slice(), 4)
+            .compile();
+
+        let mut matches = tree.find_pattern(&[Key("sport"), Single, Key("player1")]);
+        matches.sort();
+        assert_eq!(matches, vec!(
+            (vec!["sport", "golf", "player1"], &3),
+            (vec!["sport", "tennis", "player1"], &1)
+        ));
+
+        let mut matches = tree.find_pattern(&[Key("sport"), Multi]);
+        matches.sort();
+        assert_eq!(matches, vec!(
+            (vec!["sport"], &4),
+            (vec!["sport", "golf", "player1"], &3),
+            (vec!["sport", "tennis", "player1"], &1),
+            (vec!["sport", "tennis", "player2"], &2)
+        ));
+
+        assert!(tree.find_pattern(&[Single, Key("golf")]).is_empty());
+    }
+
+
+    /*
+    Test: Empty Index
+
+    Summary:
+        Checks that the empty index is stored in the root node of a compiled tree
+        The key type has no Default implementation
+     */
+    #[test]
+    fn empty_index() {
+        #[derive(Clone, PartialEq)]
+        enum Key { A }
+
+        let tree = StaticTreePlanner::new()
+            .add(&[], 1)
+            .add(&[Key::A], 2)
+            .compile();
+
+        assert_eq!(tree.find(&[]), Some(&1));
+        assert_eq!(tree.find(&[Key::A]), Some(&2));
+        assert_eq!(tree.longest_prefix(&[Key::A, Key::A]), Some((1, &2)));
+        assert_eq!(tree.find_pattern(&[]).len(), 1);
+    }
+
+
+    /*
+    Test: Drop
+
+    Summary:
+        Checks that freeing a tree drops every key and value that was moved into it
+     */
+    #[test]
+    fn drop() {
+        let key = Rc::new("a".to_string());
+        let value = Rc::new(0);
+
+        let tree = StaticTreePlanner::new()
+            .add(&[], value.clone())
+            .add(std::slice::from_ref(&key), value.clone())
+            .add(&[key.clone(), key.clone()], value.clone())
+            .compile();
+
+        assert_eq!(**tree.find(std::slice::from_ref(&key)).unwrap(), 0);
+        assert!(Rc::strong_count(&key) > 1);
+        assert_eq!(Rc::strong_count(&value), 4);
+
+        std::mem::drop(tree);
+        assert_eq!(Rc::strong_count(&key), 1);
+        assert_eq!(Rc::strong_count(&value), 1);
     }
 
-}
\ No newline at end of file
+
+    /*
+    Test: Compile Panic
+
+    Summary:
+        Checks that a key whose clone panics part-way through compile drops only the nodes that were written
+     */
+    #[test]
+    fn compile_panic() {
+        // A key that panics when the key numbered FAIL is cloned
+        // Planning clones keys too, so this is only set once compile is reached
+        thread_local! {
+            static FAIL: Cell<u32> = const { Cell::new(0) };
+        }
+
+        #[derive(PartialEq)]
+        struct Key(u32);
+        impl Clone for Key {
+            fn clone(&self) -> Self {
+                assert!(self.0 != FAIL.get(), "Key can't be cloned");
+                Key(self.0)
+            }
+        }
+
+        let value = Rc::new(0);
+        let plan = StaticTreePlanner::new()
+            .add(&[Key(1)], value.clone())
+            .add(&[Key(1), Key(2)], value.clone())
+            .add(&[Key(1), Key(3)], value.clone())
+            .add(&[Key(1), Key(3), Key(4)], value.clone());
+
+        FAIL.set(3);
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| plan.compile()));
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+
+    /*
+    Test: Threads
+
+    Summary:
+        Shares one compiled tree between several threads through an Arc
+     */
+    #[test]
+    fn threads() {
+        let mut plan: StaticTreePlanner<String, String> = StaticTreePlanner::new();
+        for i in 0..50 {
+            plan.insert_path(&format!("{}/{}", i % 5, i), '/', i.to_string());
+        }
+        let tree = Arc::new(plan.compile());
+
+        let workers: Vec<_> = (0..4).map(|thread| {
+            let tree = Arc::clone(&tree);
+            std::thread::spawn(move || {
+                for i in (thread..50).step_by(4) {
+                    assert_eq!(tree.find_path(&format!("{}/{}", i % 5, i), '/'), Some(&i.to_string()));
+                }
+
+                return tree.iter_paths('/').count();
+            })
+        }).collect();
+
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), 50);
+        }
+    }
+
+    /*
+    Test: Pool Bounds
+
+    Summary:
+        Checks that pools are limited to nodes that fit in a NodeIndex and that only pushed nodes
+        are treated as written
+     */
+    #[test]
+    fn pool_bounds() {
+        let node_size = std::mem::size_of::<TreeNode<u64, u64>>();
+
+        // Pools aren't limited to i32::MAX bytes
+        assert!(StaticTree::<u64, u64>::pool_size(100_000_000).unwrap() > i32::MAX as usize);
+        assert_eq!(StaticTree::<u64, u64>::pool_size(MAX_NODES), Some(MAX_NODES * node_size));
+        assert_eq!(StaticTree::<u64, u64>::pool_size(MAX_NODES + 1), None);
+        assert_eq!(StaticTree::<u64, u64>::pool_size(usize::MAX), None);
+
+        let tree = StaticTreePlanner::new().add(&[1u64], 2u64).compile();
+        assert_eq!(tree.node(1).value, Some(2));
+
+        // Only the root and the branch have been pushed
+        assert!(tree.is_written(1));
+        assert!(!tree.is_written(2));
+    }
+}
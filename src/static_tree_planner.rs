@@ -10,27 +10,35 @@ Summary:
 
 
 use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::ops::Index;
 
 use crate::static_tree::*;
 use crate::counted_tree_map::*;
+use crate::path;
 
 
-pub struct StaticTreePlanner<T, Idx: PartialEq + Clone + Default> {
+#[derive(Clone)]
+pub struct StaticTreePlanner<T, Idx: PartialEq + Clone> {
 
     // A TreeMap that contains the values to be inserted into the tree
     map: CountedTreeMap<T, Idx>,
+
+    // The number of values that have been added
+    len: usize
 }
 
 
 
 
 /* Implementation */
-impl<T, Idx: PartialEq + Clone + Default> StaticTreePlanner<T, Idx> {
+impl<T, Idx: PartialEq + Clone> StaticTreePlanner<T, Idx> {
     // New function
     // Returns a new, blank StaticTreePlanner
     pub fn new() -> Self {
         StaticTreePlanner {
             map: CountedTreeMap::new(),
+            len: 0
         }
     }
 
@@ -38,16 +46,70 @@ impl<T, Idx: PartialEq + Clone + Default> StaticTreePlanner<T, Idx> {
     // Adds a key-value to the internal tree
     // This function returns itself
     pub fn add(mut self, key: &[Idx], value: T) -> Self {
-        self.map.insert(key, value);
+        self.insert(key, value);
 
         self
     }
 
+    // Non-builder counterpart of add
+    // If the key already holds a value it is overwritten and the old value is returned
+    // The empty key is stored in the root node of the tree
+    pub fn insert(&mut self, key: &[Idx], value: T) -> Option<T> {
+        let old = self.map.insert(key, value);
+        if old.is_none() { self.len += 1; }
+
+        return old;
+    }
+
+    // Finds a planned value
+    // Returns None if no value has been added at the key
+    pub fn find(&self, key: &[Idx]) -> Option<&T> {
+        return self.map.find(key);
+    }
+
+    // Removes a planned value and returns it
+    // Nodes that no longer lead to a value are dropped, so they take no space in the compiled tree
+    pub fn remove(&mut self, key: &[Idx]) -> Option<T> {
+        let old = self.map.remove(key);
+        if old.is_some() { self.len -= 1; }
+
+        return old;
+    }
+
+    // Returns the number of values that have been added
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    // Returns true if no values have been added
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    // Removes every planned value
+    pub fn clear(&mut self) {
+        self.map = CountedTreeMap::new();
+        self.len = 0;
+    }
+
+    // Returns every planned (index, value) pair in depth-first order
+    pub(crate) fn entries(&self) -> Vec<(Vec<Idx>, &T)> {
+        return self.map.entries();
+    }
+
+    // Returns the root node of the planned tree
+    // The serialize module walks the nodes directly from here
+    #[cfg(feature = "serde")]
+    pub(crate) fn root(&self) -> &CountedTreeNode<T, Idx> {
+        return self.map.root_ref();
+    }
+
     // Compiles the stored TreeMap into a StaticTree
-    pub fn compile(mut self) -> StaticTree {
+    // Panics if the tree has more nodes than fit in a NodeIndex
+    pub fn compile(mut self) -> StaticTree<T, Idx> {
         // Compute output size
         let pool_size: usize = self.calculate_pool_size();
-        let tree: StaticTree = StaticTree::new(pool_size);
+        let mut tree: StaticTree<T, Idx> = StaticTree::new(pool_size);
 
 
         // Sort map
@@ -59,37 +121,42 @@ impl<T, Idx: PartialEq + Clone + Default> StaticTreePlanner<T, Idx> {
         let mut stack: VecDeque<CountedTreeNode<T, Idx>> = VecDeque::new();
 
         // Create state variables
-        let node_size = std::mem::size_of::<TreeNode<T, Idx>>() as i32; // Precompute
-        let mut pool_offset: i32 = node_size; // Placement of the first branch
-        let mut last_branch_offset: i32 = 0; // Offset of the current node's parent branch
+        // Offsets count nodes rather than bytes, and the node count has been checked to fit in a NodeIndex
+        let mut pool_offset: usize = 1; // Placement of the first branch
+        let mut last_branch_offset: usize = 0; // Offset of the current node's parent branch
 
 
         // Push root node to stack
         let mut root = self.map.root();
         root.visited = true;
-        root.list_offset = node_size;
+        root.list_offset = 1;
         stack.push_back(root);
 
 
 
         // Write root node
-        let node0 = tree.raw().get_mut::<TreeNode<T, Idx>>(0);
-        node0.key = None;
-        node0.value = None;
-        node0.list_length = stack.get(0).unwrap().nodes.len() as i32;
-        node0.list_head = node_size;
+        // The root holds the value of the empty key
+        let root_value = stack.get_mut(0).unwrap().value.take();
+        tree.push(TreeNode {
+            key: None,
+            value: root_value,
+            list_length: stack.get(0).unwrap().nodes.len() as NodeIndex,
+            list_head: 1
+        });
 
         // Write root subnodes
         for i in 0..stack.get(0).unwrap().nodes.len() {
             let sub_node = &mut stack.get_mut(0).unwrap().nodes[i];
-            let branch = tree.raw().get_mut::<TreeNode<T, Idx>>(pool_offset as usize);
 
-            branch.key          = Some(sub_node.key.clone());
-            branch.value        = sub_node.value.take();
-            branch.list_length  = sub_node.nodes.len() as i32;
-            branch.list_head    = -1;
+            // Nodes are pushed in offset order, so each one lands at pool_offset
+            tree.push(TreeNode {
+                key:            sub_node.key.clone(),
+                value:          sub_node.value.take(),
+                list_length:    sub_node.nodes.len() as NodeIndex,
+                list_head:      0
+            });
 
-            pool_offset += node_size;
+            pool_offset += 1;
         }
         
 
@@ -105,20 +172,20 @@ impl<T, Idx: PartialEq + Clone + Default> StaticTreePlanner<T, Idx> {
                     
 
                 // Set previous TreeNode::list_head value 
-                if node.nodes.len() > 0 { tree.raw().get_mut::<TreeNode<T, Idx>>(last_branch_offset as usize).list_head = pool_offset; }
+                if node.nodes.len() > 0 { tree.node_mut(last_branch_offset).list_head = pool_offset as NodeIndex; }
 
                 // Create branches by looping over nodes
                 for i in 0..node.nodes.len() {
-                    let branch = tree.raw().get_mut::<TreeNode<T, Idx>>(pool_offset as usize);
-
                     // Initialise branch values
-                    branch.key          = Some(node.nodes[i].key.clone());
-                    branch.value        = node.nodes[i].value.take();
-                    branch.list_length  = node.nodes[i].nodes.len() as i32;
-                    branch.list_head    = -1;
+                    tree.push(TreeNode {
+                        key:            node.nodes[i].key.clone(),
+                        value:          node.nodes[i].value.take(),
+                        list_length:    node.nodes[i].nodes.len() as NodeIndex,
+                        list_head:      0
+                    });
 
                     // Increment to next branch
-                    pool_offset += node_size;
+                    pool_offset += 1;
                 }
 
                 node.visited = true;
@@ -135,8 +202,8 @@ impl<T, Idx: PartialEq + Clone + Default> StaticTreePlanner<T, Idx> {
 
 
             // Calculate last branch offset
-            // list_offset is the offset of of the node + node_size
-            last_branch_offset = node.list_offset + (node_size as i32 * node.built_sub_nodes);
+            // list_offset is the offset of the node's first branch
+            last_branch_offset = node.list_offset + node.built_sub_nodes;
             node.built_sub_nodes += 1; // Increment the built node count. This technically happens on before the node is built as it's branches are built on the next iteration
 
 
@@ -153,6 +220,7 @@ impl<T, Idx: PartialEq + Clone + Default> StaticTreePlanner<T, Idx> {
 
 
     /* Helper Methods */
+    // Panics if the tree has more nodes than fit in a NodeIndex
     fn calculate_pool_size(&self) -> usize {
         let mut node_count: usize = 0;
 
@@ -173,10 +241,91 @@ impl<T, Idx: PartialEq + Clone + Default> StaticTreePlanner<T, Idx> {
             }
         };
 
-        return node_count * std::mem::size_of::<TreeNode<T, Idx>>();
+        return StaticTree::<T, Idx>::pool_size(node_count)
+            .expect("StaticTree has more nodes than fit in a NodeIndex");
+
+    }
+
+}
+// String Paths
+// Planners indexed by String can be filled with delimited paths such as "a/b/c"
+impl<T> StaticTreePlanner<T, String> {
+    // Adds a value at the delimited path
+    // This function returns itself
+    pub fn add_path(mut self, path: &str, separator: char, value: T) -> Self {
+        self.insert_path(path, separator, value);
+
+        self
+    }
+
+    // Non-builder counterpart of add_path
+    pub fn insert_path(&mut self, path: &str, separator: char, value: T) -> Option<T> {
+        return self.insert(&path::split(path, separator), value);
+    }
+
+    // Finds the planned value at the delimited path
+    pub fn find_path(&self, path: &str, separator: char) -> Option<&T> {
+        return self.find(&path::split(path, separator));
+    }
+
+    // Removes the planned value at the delimited path and returns it
+    pub fn remove_path(&mut self, path: &str, separator: char) -> Option<T> {
+        return self.remove(&path::split(path, separator));
+    }
+
+    // Returns every planned (path, value) pair, with each index joined by the separator
+    pub fn iter_paths(&self, separator: char) -> impl Iterator<Item = (String, &T)> {
+        return self.map.entries()
+            .into_iter()
+            .map(move |(index, value)| (path::join(&index, separator), value));
+    }
+}
+impl<T, Idx: PartialEq + Clone> Default for StaticTreePlanner<T, Idx> {
+    fn default() -> Self {
+        StaticTreePlanner::new()
+    }
+}
+impl<T, Idx: PartialEq + Clone> FromIterator<(Vec<Idx>, T)> for StaticTreePlanner<T, Idx> {
+    fn from_iter<I: IntoIterator<Item = (Vec<Idx>, T)>>(iter: I) -> Self {
+        let mut plan = StaticTreePlanner::new();
+        plan.extend(iter);
+
+        return plan;
+    }
+}
+impl<T, Idx: PartialEq + Clone> Extend<(Vec<Idx>, T)> for StaticTreePlanner<T, Idx> {
+    fn extend<I: IntoIterator<Item = (Vec<Idx>, T)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(&key, value);
+        }
+    }
+}
+// Planners are equal when they hold the same values at the same indices, regardless of insertion order
+impl<T: PartialEq, Idx: PartialEq + Clone> PartialEq for StaticTreePlanner<T, Idx> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len != other.len { return false; }
 
+        return self.entries().into_iter().all(|(index, value)| other.map.find(&index) == Some(value));
+    }
+}
+impl<T: Eq, Idx: PartialEq + Clone> Eq for StaticTreePlanner<T, Idx> { }
+
+// Formats the planned values as a map from full index to value
+impl<T: Debug, Idx: PartialEq + Clone + Debug> Debug for StaticTreePlanner<T, Idx> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.entries())
+            .finish()
     }
+}
+
+// Panics if the index holds no value
+impl<T, Idx: PartialEq + Clone> Index<&[Idx]> for StaticTreePlanner<T, Idx> {
+    type Output = T;
 
+    fn index(&self, index: &[Idx]) -> &T {
+        return self.find(index).expect("StaticTreePlanner has no value at the given index");
+    }
 }
 
 
@@ -185,6 +334,9 @@ impl<T, Idx: PartialEq + Clone + Default> StaticTreePlanner<T, Idx> {
 /*
 Tests:
     - Compile
+    - Find and remove
+    - Standard traits
+    - Paths
 
 Note: StaticTreePlanner::add is skipped as it is a passthrough to the CountedTreeMap below it
 */
@@ -193,7 +345,7 @@ mod tests {
     use super::*;
 
     // Helper function for checking that a given TreeNode is valid
-    fn check_node(node: &TreeNode<i32, &str>, key: Option<&str>, value: Option<i32>, list_length: i32, list_head: i32) -> bool {
+    fn check_node(node: &TreeNode<i32, &str>, key: Option<&str>, value: Option<i32>, list_length: NodeIndex, list_head: NodeIndex) -> bool {
         return node.key == key &&
             node.value == value &&
             node.list_length == list_length &&
@@ -223,22 +375,134 @@ mod tests {
         // Just the underlying memory structure
 
         // Root Node
-        assert!(check_node(tree.raw().get::<TreeNode<i32, &str>>(0), None, None, 2, 32));
+        assert!(check_node(tree.node(0), None, None, 2, 1));
 
         // "a"
-        assert!(check_node(tree.raw().get::<TreeNode<i32, &str>>(32), Some("a"), None, 1, 96));
+        assert!(check_node(tree.node(1), Some("a"), None, 1, 3));
 
         // "e"
-        assert!(check_node(tree.raw().get::<TreeNode<i32, &str>>(64), Some("e"), Some(3), 0, -1));
+        assert!(check_node(tree.node(2), Some("e"), Some(3), 0, 0));
         
         // "b"
-        assert!(check_node(tree.raw().get::<TreeNode<i32, &str>>(96), Some("b"), None, 2, 128));
+        assert!(check_node(tree.node(3), Some("b"), None, 2, 4));
 
         // "c"
-        assert!(check_node(tree.raw().get::<TreeNode<i32, &str>>(128), Some("c"), Some(1), 0, -1));
+        assert!(check_node(tree.node(4), Some("c"), Some(1), 0, 0));
 
         // "d"
-        assert!(check_node(tree.raw().get::<TreeNode<i32, &str>>(160), Some("d"), Some(2), 0, -1));
+        assert!(check_node(tree.node(5), Some("d"), Some(2), 0, 0));
+    }
+
+
+    /*
+    Test: Find and Remove
+
+    Summary:
+        Checks that removed values are left out of the compiled tree along with the nodes that led to them
+     */
+    #[test]
+    fn find_remove() {
+        let mut plan: StaticTreePlanner<i32, &str> = StaticTreePlanner::new()
+            .add(vec!["a", "b", "c"].as_slice(), 1)
+            .add(vec!["a", "d"].as_slice(), 2)
+            .add(vec![].as_slice(), 3);
+
+        assert_eq!(plan.find(vec!["a", "b", "c"].as_slice()), Some(&1));
+        assert_eq!(plan.find(vec!["a", "b"].as_slice()), None);
+
+        assert_eq!(plan.remove(vec!["a", "b", "c"].as_slice()), Some(1));
+        assert_eq!(plan.remove(vec!["a", "b", "c"].as_slice()), None);
+        assert_eq!(plan.remove(vec!["a"].as_slice()), None);
+        assert_eq!(plan.remove(vec![].as_slice()), Some(3));
+        assert_eq!(plan.find(vec!["a", "b", "c"].as_slice()), None);
+        assert_eq!(plan.len(), 1);
+
+        // Only the root, "a" and "d" are compiled
+        let tree = plan.compile();
+        assert_eq!(tree.node_count(), 3);
+        assert_eq!(tree.find(vec!["a", "d"].as_slice()), Some(&2));
+        assert_eq!(tree.find(vec![].as_slice()), None);
     }
 
-}
\ No newline at end of file
+
+    /*
+    Test: Standard Traits
+
+    Summary:
+        Checks collecting, extending, cloning, comparing, indexing, clearing and formatting a StaticTreePlanner
+     */
+    #[test]
+    fn standard_traits() {
+        let mut plan: StaticTreePlanner<i32, &str> = vec!((vec!["a", "b"], 1), (vec!["c"], 2)).into_iter().collect();
+        plan.extend(vec!((vec!["a", "b"], 3), (vec!["d"], 4)));
+        assert_eq!(plan.len(), 3);
+
+        let copy = plan.clone();
+        assert_eq!(format!("{:?}", copy), r#"{["a", "b"]: 3, ["c"]: 2, ["d"]: 4}"#);
+
+        // Equality ignores insertion order
+        let reordered: StaticTreePlanner<i32, &str> = vec!((vec!["d"], 4), (vec!["c"], 2), (vec!["a", "b"], 3)).into_iter().collect();
+        assert_eq!(plan, reordered);
+        assert_ne!(plan, reordered.clone().add(&["a"], 5));
+        assert_ne!(plan, StaticTreePlanner::new().add(&["a", "b"], 3).add(&["c"], 2).add(&["d"], 5));
+
+        // Index
+        assert_eq!(plan[&["a", "b"][..]], 3);
+        assert!(std::panic::catch_unwind(|| plan[&["a"][..]]).is_err());
+
+        let tree = copy.compile();
+        assert_eq!(*tree.find(vec!["a", "b"].as_slice()).unwrap(), 3);
+        assert_eq!(*tree.find(vec!["d"].as_slice()).unwrap(), 4);
+
+        // Clear
+        plan.clear();
+        assert!(plan.is_empty());
+        assert_eq!(plan, StaticTreePlanner::default());
+        assert!(plan.compile().find(vec!["a", "b"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Paths
+
+    Summary:
+        Checks planning, finding and removing with delimited paths and looking them up in the compiled StaticTree
+     */
+    #[test]
+    fn paths() {
+        let plan: StaticTreePlanner<i32, String> = StaticTreePlanner::new()
+            .add_path("a/b/c", '/', 1)
+            .add_path(r"a/b\\/c", '/', 2)
+            .add_path("d", '/', 3);
+
+        let planned: Vec<(String, &i32)> = plan.iter_paths('.').collect();
+        assert_eq!(planned, vec!(
+            ("a.b.c".to_string(), &1),
+            (r"a.b\\.c".to_string(), &2),
+            ("d".to_string(), &3)
+        ));
+
+        assert_eq!(plan.find_path(r"a.b\\.c", '.'), Some(&2));
+        assert_eq!(plan.find_path("a/b", '/'), None);
+
+        let mut removed = plan.clone();
+        assert_eq!(removed.remove_path("d", '/'), Some(3));
+        assert_eq!(removed.remove_path("d", '/'), None);
+        assert_eq!(removed.find_path("d", '/'), None);
+        assert_eq!(removed.len(), 2);
+
+        let tree = plan.compile();
+        assert_eq!(tree.find_path("a/b/c", '/'), Some(&1));
+        assert_eq!(tree.find_path(r"a.b\\.c", '.'), Some(&2));
+        assert_eq!(tree.find_path("a/b", '/'), None);
+
+        let mut paths: Vec<(String, &i32)> = tree.iter_paths('/').collect();
+        paths.sort();
+        assert_eq!(paths, vec!(
+            ("a/b/c".to_string(), &1),
+            (r"a/b\\/c".to_string(), &2),
+            ("d".to_string(), &3)
+        ));
+    }
+
+}
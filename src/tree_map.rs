@@ -5,45 +5,56 @@ Summary:
     The internal tree_map module contains defenitions for a basic, unoptimised tree map stucture
     This structure is used by the StaticTreePlanner structure to optimise the tree structure
 */
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::ops::Index;
 
+use crate::pattern::{self, PatternSegment};
+use crate::path;
+use crate::child_storage::{ChildStorage, Children, Lookup, Unsorted, Sorted};
+use crate::cursor::{Cursor, CursorMut};
 
-// TreeNode<T, Idx>
+use std::ops::RangeBounds;
+
+
+// TreeNode<T, Idx, S>
 // T - The value to store
 // Idx - The index type. Must be equatable and clonable
+// S - The storage strategy for sub-nodes. See the child_storage module
 //
 // This is a structure that acts as the storage for a basic tree map
 // The structure formulates a recursive tree pattern where each node has references to child nodes
 // This does not allow for reverse lookups
-pub struct TreeNode<T, Idx: PartialEq + Clone>  {
-    // The key at the current depth
-    // This is a subset of the total index
-    // This is owned by the TreeNode type
-    key: Idx,
-
+pub struct TreeNode<T, Idx, S: ChildStorage<T, Idx> = Unsorted>  {
     // The value stored by the tree node
     // Not all TreeNodes store values as some are intermediaries
     // The TreeNode owns T
-    value: Option<T>,
+    pub(crate) value: Option<T>,
 
-    // A vector of owned TreeNodes
-    // This represents the sub-nodes underneath this node
-    nodes: Vec<TreeNode<T, Idx>>
+    // The owned sub-nodes underneath this node
+    // Each sub-node is stored with the key at its depth, which is a subset of the total index
+    pub(crate) nodes: S::Children
 }
 
 
-// TreeMap<T, Idx>
+// TreeMap<T, Idx, S>
 // T - The value type to store
-// Idx - the index type. Must be equatable and clonable and defaultable
+// Idx - the index type. Must be equatable and clonable
+// S - The storage strategy for sub-nodes. Defaults to Unsorted which scans a Vec
 //
 // This structure acts as a container for the caller to interface with TreeNodes
-// It can be considered a partial TreeNode but without the key and value fields
-pub struct TreeMap<T, Idx: PartialEq + Clone + Default> {
+// It can be considered a partial TreeNode but without the value field
+pub struct TreeMap<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
     // The top-most node of the tree
-    // This node has no value or key and is the root of the tree
+    // This node has no key and holds the value of the empty index
     //
     // Note that it would be more memory efficient to use the TreeMap as an implicit root node
     // however that would require more code duplication
-    head: TreeNode<T, Idx>
+    head: TreeNode<T, Idx, S>,
+
+    // The number of values stored in the tree
+    // This is kept up to date by every operation that adds or removes a value
+    len: usize
 }
 
 
@@ -52,20 +63,27 @@ pub struct TreeMap<T, Idx: PartialEq + Clone + Default> {
 /*
 Implementations
 */
-impl<T, Idx: PartialEq + Clone> TreeNode<T, Idx> {
+impl<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> TreeNode<T, Idx, S> {
+
+    // Creates a new node with no value and no sub-nodes
+    pub(crate) fn new() -> Self {
+        TreeNode {
+            value: None,
+            nodes: S::Children::default()
+        }
+    }
 
     // Insets a value into the tree
     // If the index doesn't exist then a new path is created
-    // otherwise the value is overwritten
+    // otherwise the value is overwritten and the old value is returned
     //
     // The index must be provided as a reference to a slice of keys that formulate the index
-    pub fn insert(&mut self, index: &[Idx], value: T) {
+    pub fn insert(&mut self, index: &[Idx], value: T) -> Option<T> {
         // Check if the length of the index is 0
         // In this case we have reached the end of the slice and there are no more indices
         // Hence we can set the value
         if index.len() == 0 {
-            self.value = Some(value); // Set value
-            return; // Return early
+            return self.value.replace(value); // Set value and return early
         }
 
         // Get the first key in the index slice
@@ -73,202 +91,2186 @@ impl<T, Idx: PartialEq + Clone> TreeNode<T, Idx> {
 
 
         // Get next node
-        let next = match self.find_node_mut(key) {
-            // If we found a node just return it
-            Some(n) => n,
-
-            // Otherwise we need to create a new node
-            None => {
-                // Create new tree node
-                let n = TreeNode {
-                    key: (*key).clone(),
-                    value: None,
-                    nodes: vec!()
-                };
-
-                // Add new node to the nodes list
-                // This takes ownership of n
-                self.nodes.push(n);
-
-                // Return reference to n
-                self.nodes.last_mut().unwrap()
-            }
-        };
+        // If the node doesn't exist then the storage creates a new, empty node for it
+        let next = self.nodes.get_or_insert(key);
 
         // With this new node we can recursivly call insert again
-        next.insert(&index[1..], value);
+        return next.insert(&index[1..], value);
     }
 
     // Iterates over the tree and finds a value at the given index
     // If the value exists then a reference to it is returned
     // Otherwise None is returned
+    //
+    // The index can be any iterator of borrowed keys, see child_storage::Lookup
     #[inline(never)]
-    pub fn find(&self, index: &[Idx]) -> Option<&T> {
-        // Check if we have consumed the whole index
-        // In this case we have no more sub-nodes to iterate over
+    pub fn find<'q, Q, I>(&self, index: I) -> Option<&T>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        return self.find_descendant(index)?.value.as_ref();
+    }
+
+    // Mutable counterpart of find
+    // If the value exists then a mutable reference to it is returned
+    pub(crate) fn find_mut<'q, Q, I>(&mut self, index: I) -> Option<&mut T>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        return self.find_descendant_mut(index)?.value.as_mut();
+    }
+
+    // Walks down the tree along the index and returns the deepest value found
+    // along with the number of keys that were matched to reach it
+    // The value held by this node is matched at depth 0
+    pub(crate) fn longest_prefix<'q, Q, I>(&self, index: I) -> Option<(usize, &T)>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        let mut node = self;
+        let mut longest = self.value.as_ref().map(|value| (0, value));
+
+        for (depth, key) in index.into_iter().enumerate() {
+            node = match node.nodes.lookup(key) {
+                Some(n) => n,
+                None => { break; }
+            };
+
+            // Record the deepest value so far
+            if let Some(value) = node.value.as_ref() {
+                longest = Some((depth + 1, value));
+            }
+        }
+
+        return longest;
+    }
+
+    // Collects every value that matches the pattern into out
+    // path holds the index of this node and is restored before returning
+    pub(crate) fn find_pattern<'a>(&'a self, pattern: &[PatternSegment<Idx>], path: &mut Vec<Idx>, out: &mut Vec<(Vec<Idx>, &'a T)>) {
+        match pattern.first() {
+            // Reached the end of the pattern so this node is a match
+            None => {
+                if let Some(value) = self.value.as_ref() {
+                    out.push((path.clone(), value));
+                }
+            },
+
+            // Only the node with the matching key is followed
+            Some(PatternSegment::Key(key)) => {
+                if let Some(node) = self.find_node(key) {
+                    path.push(key.clone());
+                    node.find_pattern(&pattern[1..], path, out);
+                    path.pop();
+                }
+            },
+
+            // Every sub-node is followed
+            Some(PatternSegment::Single) => {
+                for (key, node) in self.nodes.iter() {
+                    path.push(key.clone());
+                    node.find_pattern(&pattern[1..], path, out);
+                    path.pop();
+                }
+            },
+
+            // This node and everything underneath it matches
+            Some(PatternSegment::Multi) => {
+                for (relative, value) in Iter::new(self) {
+                    let mut full = path.clone();
+                    full.extend(relative);
+
+                    out.push((full, value));
+                }
+            }
+        };
+    }
+
+    // Walks down the tree and returns the node at the given index
+    // Unlike find this returns the node itself rather than its value
+    pub(crate) fn find_descendant<'q, Q, I>(&self, index: I) -> Option<&TreeNode<T, Idx, S>>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        let mut node = self;
+        for key in index {
+            node = node.nodes.lookup(key)?;
+        }
+
+        return Some(node);
+    }
+    // Mutable counterpart of find_descendant
+    pub(crate) fn find_descendant_mut<'q, Q, I>(&mut self, index: I) -> Option<&mut TreeNode<T, Idx, S>>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        let mut node = self;
+        for key in index {
+            node = node.nodes.lookup_mut(key)?;
+        }
+
+        return Some(node);
+    }
+
+    // Counts the number of values held by this node and its sub-nodes
+    pub(crate) fn count(&self) -> usize {
+        let own = if self.value.is_some() { 1 } else { 0 };
+
+        return own + self.nodes.iter().map(|(_, node)| node.count()).sum::<usize>();
+    }
+
+    // Removes the value at the given index and returns it
+    // Any node along the path that is left with no value and no sub-nodes is pruned
+    // If the index doesn't exist then None is returned
+    pub(crate) fn remove(&mut self, index: &[Idx]) -> Option<T> {
+        // Reached the end of the index so take the value out of this node
         if index.len() == 0 {
-            return self.value.as_ref();
+            return self.value.take();
         }
 
-        // Get first key in index
+        // Get the next node
         let key = index.first().unwrap();
+        let next = self.find_node_mut(key)?;
+
+        // Recursivly remove the value
+        let value = next.remove(&index[1..]);
+
+        // Prune the next node if it has been emptied
+        if next.is_empty() {
+            self.nodes.remove(key);
+        }
+
+        return value;
+    }
+
+    // Detaches the node at the given index, along with all of its sub-nodes, and returns it
+    // Ancestors that are left empty are pruned in the same way as remove
+    // The index must contain at least one key
+    pub(crate) fn remove_node(&mut self, index: &[Idx]) -> Option<TreeNode<T, Idx, S>> {
+        let key = index.first()?;
 
+        // The next node is the requested node, so it can be detached directly
+        if index.len() == 1 {
+            return self.nodes.remove(key);
+        }
+
+        let next = self.find_node_mut(key)?;
+        let node = next.remove_node(&index[1..]);
+
+        if next.is_empty() {
+            self.nodes.remove(key);
+        }
 
-        // Find the node
-        let next = match self.find_node(key) {
-            // If we found the node, return it
-            Some(n) => n,
+        return node;
+    }
+
+    // Merges another node into this one
+    // Sub-nodes that only exist in other are moved across without being cloned
+    // If both nodes hold a value then resolve is called with the index, this node's value and the other value
+    //
+    // path holds the index of this node and is restored before returning
+    pub(crate) fn merge<F: FnMut(&[Idx], T, T) -> T>(&mut self, other: TreeNode<T, Idx, S>, path: &mut Vec<Idx>, resolve: &mut F) {
+        let TreeNode { value, nodes } = other;
 
-            // Otherwise the node doesn't exist
-            // So return None
-            None => { return None; }
+        self.value = match (self.value.take(), value) {
+            (Some(mine), Some(theirs)) => Some(resolve(path, mine, theirs)),
+            (mine, theirs) => mine.or(theirs)
         };
 
-        // Recursivly find the value
-        return next.find(&index[1..]);
+        for (key, node) in nodes.into_entries() {
+            match self.nodes.get_mut(&key) {
+                // Both trees have this sub-node so it must be merged
+                Some(existing) => {
+                    path.push(key);
+                    existing.merge(node, path, resolve);
+                    path.pop();
+                },
 
+                // Otherwise the whole subtree is grafted in
+                None => { self.nodes.insert(key, node); }
+            };
+        }
     }
 
+    // Keeps only the values for which f returns true
+    // Sub-nodes that are left without a value or sub-nodes are pruned
+    //
+    // path holds the index of this node and is restored before returning
+    pub(crate) fn retain<F: FnMut(&[Idx], &mut T) -> bool>(&mut self, path: &mut Vec<Idx>, f: &mut F) {
+        if let Some(value) = self.value.as_mut() {
+            if !f(path, value) {
+                self.value = None;
+            }
+        }
 
+        self.nodes.retain(|key, node| {
+            path.push(key.clone());
+            node.retain(path, f);
+            path.pop();
 
-    /* Internal Methods */
-    // Finds a requested node in the nodes list
-    // Returns None if the node doesn't exist
-    pub fn find_node(&self, key: &Idx) -> Option<&TreeNode<T, Idx>> {
-        // Iterate over the self.nodes vector
-        for node in &self.nodes {
-            if node.key == *key { // Check if key matches
-                return Some(node); // Return reference to node
+            !node.is_empty()
+        });
+    }
+
+    // Converts this node and its sub-nodes into a node with a different value type
+    // The shape of the tree is kept
+    pub(crate) fn map_values<U, F: FnMut(T) -> U>(self, f: &mut F) -> TreeNode<U, Idx, S> where S: ChildStorage<U, Idx> {
+        let mut out: TreeNode<U, Idx, S> = TreeNode::new();
+        out.value = self.value.map(&mut *f);
+
+        for (key, node) in self.nodes.into_entries() {
+            out.nodes.insert(key, node.map_values(f));
+        }
+
+        return out;
+    }
+
+    // Same as map_values but values that f maps to None are removed
+    // Sub-nodes that are left without a value or sub-nodes are pruned
+    pub(crate) fn filter_map_values<U, F: FnMut(T) -> Option<U>>(self, f: &mut F) -> TreeNode<U, Idx, S> where S: ChildStorage<U, Idx> {
+        let mut out: TreeNode<U, Idx, S> = TreeNode::new();
+        out.value = self.value.and_then(&mut *f);
+
+        for (key, node) in self.nodes.into_entries() {
+            let node = node.filter_map_values(f);
+
+            if !node.is_empty() {
+                out.nodes.insert(key, node);
             }
-        };
+        }
 
-        return None;
+        return out;
     }
-    // Mutable counterpart of find_node
-    fn find_node_mut(&mut self, key: &Idx) -> Option<&mut TreeNode<T, Idx>> {
-        for node in &mut self.nodes {
-            if node.key == *key {
-                return Some(node);
+
+    // Returns true if this node has no value and no sub-nodes
+    // Such a node is only an intermediary and can be pruned
+    pub(crate) fn is_empty(&self) -> bool {
+        return self.value.is_none() && self.nodes.len() == 0;
+    }
+
+
+
+    /* Internal Methods */
+    // Finds the last value in depth-first order underneath this node and pushes its relative index onto index
+    // Sub-nodes are tried from the last one back, so one without any values is skipped rather than ending the search
+    // Empty nodes are pruned, so this normally follows a single path down
+    fn last_value(&self, index: &mut Vec<Idx>) -> Option<&T> {
+        for (key, node) in self.nodes.iter().rev() {
+            index.push(key.clone());
+            if let Some(value) = node.last_value(index) {
+                return Some(value);
             }
-        };
+            index.pop();
+        }
+
+        return self.value.as_ref();
+    }
 
-        return None;
+    // Finds a requested node in the nodes list
+    // Returns None if the node doesn't exist
+    pub fn find_node(&self, key: &Idx) -> Option<&TreeNode<T, Idx, S>> {
+        // The lookup is delegated to the storage strategy
+        return self.nodes.get(key);
+    }
+    // Mutable counterpart of find_node
+    fn find_node_mut(&mut self, key: &Idx) -> Option<&mut TreeNode<T, Idx, S>> {
+        return self.nodes.get_mut(key);
     }
 }
 
 
 
-impl<T, Idx: PartialEq + Clone + Default> TreeMap<T, Idx> {
+impl<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> TreeMap<T, Idx, S> {
     pub fn new() -> Self {
         TreeMap {
-            head: TreeNode {
-                key: Idx::default(),
-                value: None,
-                nodes: vec!()
-            }
+            head: TreeNode::new(),
+            len: 0
         }
     }
 
     // Inserts a value into the tree
     // This takes a slice of keys that make up the whole index
     // And the value to insert
-    // If the value already exists it is ovewritten
-    pub fn insert(&mut self, index: &[Idx], value: T) {
-        // Check that index actually contains values
-        if index.len() == 0 { return; }
-
+    // If the value already exists it is ovewritten and the old value is returned
+    // The empty index is a valid key whose value is held by the root
+    pub fn insert(&mut self, index: &[Idx], value: T) -> Option<T> {
         // Insert the key
-        self.head.insert(index, value);
+        let old = self.head.insert(index, value);
+        if old.is_none() { self.len += 1; }
+
+        return old;
+    }
+
+    // Returns the number of values stored in the tree
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    // Returns true if the tree holds no values
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    // Removes every value from the tree
+    pub fn clear(&mut self) {
+        self.head = TreeNode::new();
+        self.len = 0;
     }
 
     // Finds and returns a value from the tree
     // If the value exists then a reference to it is returned
     // Otherwise None is returned
-    pub fn find(&self, index: &[Idx]) -> Option<&T> {
-        if index.len() == 0 { return None; }
-
+    //
+    // The index can be any iterator of keys borrowed in the same way as HashMap::get
+    // so a TreeMap<T, String> can be searched with ["a", "b"] or path.split('/') without allocating
+    pub fn find<'q, Q, I>(&self, index: I) -> Option<&T>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
         return self.head.find(index);
     }
-}
 
+    // Mutable counterpart of find
+    // If the value exists then a mutable reference to it is returned
+    pub fn find_mut<'q, Q, I>(&mut self, index: I) -> Option<&mut T>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        return self.head.find_mut(index);
+    }
 
+    // Gets the entry at the given index for in-place manipulation
+    // The path is only walked once, an Occupied entry holds the node with the value
+    // and a Vacant entry holds the deepest existing node along with the remaining keys
+    pub fn entry(&mut self, index: &[Idx]) -> Entry<'_, T, Idx, S> {
+        // Walk down the existing nodes as far as the index allows
+        let mut node = &mut self.head;
+        let len = &mut self.len;
+        let mut depth = 0;
+        while depth < index.len() {
+            // Each level is looked up once. The position is found first so that node
+            // is only reborrowed when the walk continues, and fetching by position is O(1)
+            let Some(position) = node.nodes.position(&index[depth]) else { break; };
 
+            node = node.nodes.get_index_mut(position).unwrap().1;
+            depth += 1;
+        }
 
-/*
-Tests:
-    - TreeMap::insert
-    - TreeMap::find     On valid target (Return Some)
-    - TreeMap::find     On invalid target (Return None)
+        // The whole index exists and holds a value
+        if depth == index.len() && node.value.is_some() {
+            return Entry::Occupied(OccupiedEntry { node });
+        }
 
+        return Entry::Vacant(VacantEntry {
+            node,
+            len,
+            remaining: index[depth..].to_vec()
+        });
+    }
 
-*/
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Finds the deepest value along the index
+    // Returns the number of keys matched to reach the value and a reference to it
+    // If no value exists along the index then None is returned
+    pub fn longest_prefix<'q, Q, I>(&self, index: I) -> Option<(usize, &T)>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        return self.head.longest_prefix(index);
+    }
 
-    /*
-    Test: Insert
+    // Finds every value whose index matches the pattern
+    // Each segment of the pattern is either a key, a single level wildcard or a multi level wildcard
+    // Matches are returned in depth-first order
+    //
+    // PatternSegment::Multi must only be used as the last segment
+    pub fn find_pattern(&self, pattern: &[PatternSegment<Idx>]) -> Vec<(Vec<Idx>, &T)> {
+        pattern::validate(pattern);
 
-    Summary:
-        This test is to ensure that TreeMAp<i32, &str> can correctly insert elements without panicing.
-        It does not check that the elemnt has been inserted correctly
-     */
-    #[test]
-    fn insert() {
-        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        let mut out = vec!();
+        self.head.find_pattern(pattern, &mut vec!(), &mut out);
 
-        // Check insert
-        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+        return out;
+    }
 
-        // Check insert along created path
-        map.insert(vec!["a", "b", "d"].as_slice(), 1);
+    // Returns a cursor positioned at the root of the tree
+    // The cursor can be moved up, down and between siblings
+    pub fn cursor(&self) -> Cursor<'_, T, Idx, S> {
+        return Cursor::new(&self.head);
+    }
 
-        // Insert along new path
-        map.insert(vec!["e", "f"].as_slice(), 1);
+    // Mutable counterpart of cursor
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T, Idx, S> {
+        return CursorMut::new(&mut self.head, &mut self.len);
+    }
 
-        // Long insert
-        map.insert(vec!["e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p"].as_slice(), 1);
+    // Returns a depth-first iterator over every (index, value) pair in the tree
+    // Sub-nodes are visited in insertion order
+    pub fn iter(&self) -> Iter<'_, T, Idx, S> {
+        return Iter::new(&self.head);
     }
 
+    // Returns a breadth-first iterator over every (index, value) pair in the tree
+    pub fn iter_breadth_first(&self) -> BreadthFirstIter<'_, T, Idx, S> {
+        return BreadthFirstIter::new(&self.head);
+    }
 
-    /*
-    Test: Find
+    // Mutable counterpart of iter
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, Idx, S> {
+        return IterMut::new(&mut self.head);
+    }
 
-    Summary:
-        Validates that TreeMap can correctly read back inserted elements
-     */
-    #[test]
-    fn find() {
-        let mut map: TreeMap<i32, &str> = TreeMap::new();
+    // Returns a depth-first iterator over the index of every value in the tree
+    pub fn keys(&self) -> Keys<'_, T, Idx, S> {
+        return Keys { inner: self.iter() };
+    }
 
-        map.insert(vec!["a", "b", "c"].as_slice(), 1);
-        map.insert(vec!["a", "b", "d"].as_slice(), 2);
-        map.insert(vec!["e", "f"].as_slice(), 3);
+    // Returns a depth-first iterator over every value in the tree
+    pub fn values(&self) -> Values<'_, T, Idx, S> {
+        return Values::new(&self.head);
+    }
 
+    // Returns a borrowed view of the subtree underneath a prefix
+    // Indices used with the view are relative to the prefix
+    // If the prefix doesn't exist then None is returned
+    pub fn subtree<'q, Q, I>(&self, prefix: I) -> Option<SubTree<'_, T, Idx, S>>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        let node = self.head.find_descendant(prefix)?;
 
-        // Check response
-        assert_eq!(*map.find(vec!["a", "b", "c"].as_slice()).unwrap(), 1);
-        assert_eq!(*map.find(vec!["a", "b", "d"].as_slice()).unwrap(), 2);
-        assert_eq!(*map.find(vec!["e", "f"].as_slice()).unwrap(), 3);
+        return Some(SubTree { node });
     }
 
+    // Mutable counterpart of subtree
+    pub fn subtree_mut<'q, Q, I>(&mut self, prefix: I) -> Option<SubTreeMut<'_, T, Idx, S>>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        let node = self.head.find_descendant_mut(prefix)?;
 
-    /*
-    Test: Find Invalid items
+        return Some(SubTreeMut { node, len: &mut self.len });
+    }
 
-    Summary:
-        Validates that TreeMap will error correctly when find is called on an unknown index
-     */
-    #[test]
-    fn find_invalid() {
-        let mut map: TreeMap<i32, &str> = TreeMap::new();
-        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+    // Merges another tree into this one
+    // The nodes of the other tree are moved in without cloning
+    // Where both trees hold a value at the same index resolve is called with the index,
+    // the value from this tree and the value from the other tree, and its result is kept
+    pub fn merge<F: FnMut(&[Idx], T, T) -> T>(&mut self, other: TreeMap<T, Idx, S>, mut resolve: F) {
+        // Every conflict combines two values into one
+        let mut conflicts = 0;
+        self.head.merge(other.head, &mut vec!(), &mut |index: &[Idx], mine, theirs| {
+            conflicts += 1;
+            resolve(index, mine, theirs)
+        });
 
-        // Wrong from root
-        assert_eq!(map.find(vec!["e", "f"].as_slice()).is_none(), true);
+        self.len = self.len + other.len - conflicts;
+    }
 
-        // Wrong along index
-        assert_eq!(map.find(vec!["a", "b", "z"].as_slice()).is_none(), true);
+    // Returns a new tree that contains the values of both trees
+    // Neither tree is modified, so both are cloned and the clone of the other tree is merged in node by node
+    // Where both trees hold a value at the same index resolve is called with the index and both values
+    pub fn union<F: FnMut(&[Idx], &T, &T) -> T>(&self, other: &TreeMap<T, Idx, S>, mut resolve: F) -> TreeMap<T, Idx, S>
+    where T: Clone, S::Children: Clone {
+        let mut out = self.clone();
+        out.merge(other.clone(), |index, mine, theirs| resolve(index, &mine, &theirs));
+
+        return out;
+    }
+
+    // Keeps only the values for which f returns true
+    // f is called with the index and a mutable reference to each value, in depth-first order
+    // Branches that are left empty are pruned
+    pub fn retain<F: FnMut(&[Idx], &mut T) -> bool>(&mut self, mut f: F) {
+        let len = &mut self.len;
+        self.head.retain(&mut vec!(), &mut |index: &[Idx], value: &mut T| {
+            let keep = f(index, value);
+            if !keep { *len -= 1; }
+
+            keep
+        });
+    }
+
+    // Converts the tree into a tree with a different value type by calling f on every value
+    // The shape of the tree is kept
+    pub fn map_values<U, F: FnMut(T) -> U>(self, mut f: F) -> TreeMap<U, Idx, S> where S: ChildStorage<U, Idx> {
+        return TreeMap {
+            head: self.head.map_values(&mut f),
+            len: self.len
+        };
+    }
+
+    // Same as map_values but values that f maps to None are removed
+    // Branches that are left empty are pruned
+    pub fn filter_map_values<U, F: FnMut(T) -> Option<U>>(self, mut f: F) -> TreeMap<U, Idx, S> where S: ChildStorage<U, Idx> {
+        return TreeMap::from_root(self.head.filter_map_values(&mut f));
+    }
+
+    // Removes a value from the tree and returns it
+    // Intermediate nodes that are left with no value and no children are pruned
+    // If the value doesn't exist then None is returned
+    pub fn remove(&mut self, index: &[Idx]) -> Option<T> {
+        let value = self.head.remove(index);
+        if value.is_some() { self.len -= 1; }
+
+        return value;
+    }
+
+    // Removes the whole subtree underneath an index and returns it as a new TreeMap
+    // Paths in the returned map are relative to the index
+    // The value stored at the index itself is kept in the root node of the returned map
+    // If the index doesn't exist then None is returned
+    // The empty index removes the whole tree
+    pub fn remove_prefix(&mut self, index: &[Idx]) -> Option<TreeMap<T, Idx, S>> {
+        if index.len() == 0 {
+            return Some(std::mem::take(self));
+        }
+
+        let node = self.head.remove_node(index)?;
+        let removed = TreeMap::from_root(node);
+        self.len -= removed.len;
+
+        return Some(removed);
+    }
+
+    // Creates a TreeMap with an existing node as its root
+    // This is used when a subtree is detached from another map
+    // The values in the subtree are counted, so this is O(n)
+    pub(crate) fn from_root(head: TreeNode<T, Idx, S>) -> Self {
+        TreeMap {
+            len: head.count(),
+            head
+        }
+    }
+
+    // Returns the top-most node of the tree
+    // The serialize module walks the nodes directly from here
+    #[cfg(feature = "serde")]
+    pub(crate) fn root(&self) -> &TreeNode<T, Idx, S> {
+        return &self.head;
+    }
+}
+// Ordered TreeMap
+// When sub-nodes are stored with the Sorted strategy every level is kept in key order
+// This means iteration comes out in lexicographic index order and sibling keys can be queried by range
+impl<T, Idx: Ord + Clone> TreeMap<T, Idx, Sorted> {
+    // Returns the sub-nodes of a prefix whose key falls within the range
+    // Each sub-node is returned with its key as a view of the subtree underneath it
+    // If the prefix doesn't exist then the iterator is empty
+    pub fn range<R: RangeBounds<Idx>>(&self, prefix: &[Idx], range: R) -> Range<'_, T, Idx> {
+        let nodes = match self.head.find_descendant(prefix) {
+            Some(node) => node.nodes.range(range),
+            None => &[]
+        };
+
+        return Range { inner: nodes.iter() };
+    }
+
+    // Returns the lexicographically first (index, value) pair underneath the prefix
+    // The prefix itself is included and comes before everything underneath it
+    pub fn first(&self, prefix: &[Idx]) -> Option<(Vec<Idx>, &T)> {
+        let node = self.head.find_descendant(prefix)?;
+
+        // The depth-first iterator visits sub-nodes in key order
+        let (relative, value) = Iter::new(node).next()?;
+
+        let mut index = prefix.to_vec();
+        index.extend(relative);
+
+        return Some((index, value));
+    }
+
+    // Returns the lexicographically last (index, value) pair underneath the prefix
+    pub fn last(&self, prefix: &[Idx]) -> Option<(Vec<Idx>, &T)> {
+        let node = self.head.find_descendant(prefix)?;
+        let mut index = prefix.to_vec();
+
+        let value = node.last_value(&mut index)?;
+
+        return Some((index, value));
+    }
+}
+// String Paths
+// Trees indexed by String can be addressed with delimited paths such as "a/b/c"
+// The separator is given on each call and can be escaped with a backslash, see the path module
+impl<T, S: ChildStorage<T, String>> TreeMap<T, String, S> {
+    // Inserts a value at the delimited path
+    // If the value already exists it is ovewritten and the old value is returned
+    pub fn insert_path(&mut self, path: &str, separator: char, value: T) -> Option<T> {
+        return self.insert(&path::split(path, separator), value);
+    }
+
+    // Finds the value at the delimited path
+    pub fn find_path(&self, path: &str, separator: char) -> Option<&T> {
+        return self.find(&path::split(path, separator));
+    }
+
+    // Mutable counterpart of find_path
+    pub fn find_path_mut(&mut self, path: &str, separator: char) -> Option<&mut T> {
+        return self.find_mut(&path::split(path, separator));
+    }
+
+    // Removes the value at the delimited path and returns it
+    pub fn remove_path(&mut self, path: &str, separator: char) -> Option<T> {
+        return self.remove(&path::split(path, separator));
+    }
+
+    // Returns a depth-first iterator over every (path, value) pair, with each index joined by the separator
+    pub fn iter_paths(&self, separator: char) -> impl Iterator<Item = (String, &T)> {
+        return self.iter().map(move |(index, value)| (path::join(&index, separator), value));
+    }
+}
+impl<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Default for TreeMap<T, Idx, S> {
+    fn default() -> Self {
+        TreeMap::new()
+    }
+}
+
+
+
+/*
+Standard Traits
+*/
+impl<T: Clone, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Clone for TreeNode<T, Idx, S> where S::Children: Clone {
+    fn clone(&self) -> Self {
+        TreeNode {
+            value: self.value.clone(),
+            nodes: self.nodes.clone()
+        }
+    }
+}
+impl<T: Clone, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Clone for TreeMap<T, Idx, S> where S::Children: Clone {
+    fn clone(&self) -> Self {
+        TreeMap {
+            head: self.head.clone(),
+            len: self.len
+        }
+    }
+}
+
+// Two trees are equal if they hold the same values at the same indices
+// The order of sub-nodes is not compared, so Unsorted trees built in different orders are still equal
+impl<T: PartialEq, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> PartialEq for TreeMap<T, Idx, S> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len != other.len { return false; }
+
+        return self.iter().all(|(index, value)| other.head.find(&index) == Some(value));
+    }
+}
+impl<T: Eq, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Eq for TreeMap<T, Idx, S> { }
+
+// Formats the tree as a map from full index to value
+impl<T: Debug, Idx: PartialEq + Clone + Debug, S: ChildStorage<T, Idx>> Debug for TreeMap<T, Idx, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.iter())
+            .finish()
+    }
+}
+
+impl<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> FromIterator<(Vec<Idx>, T)> for TreeMap<T, Idx, S> {
+    fn from_iter<I: IntoIterator<Item = (Vec<Idx>, T)>>(iter: I) -> Self {
+        let mut map = TreeMap::new();
+        map.extend(iter);
+
+        return map;
+    }
+}
+impl<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Extend<(Vec<Idx>, T)> for TreeMap<T, Idx, S> {
+    fn extend<I: IntoIterator<Item = (Vec<Idx>, T)>>(&mut self, iter: I) {
+        for (index, value) in iter {
+            self.insert(&index, value);
+        }
+    }
+}
+
+// Looks up a value in the same way as find
+// Panics if the index doesn't hold a value
+impl<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Index<&[Idx]> for TreeMap<T, Idx, S> {
+    type Output = T;
+
+    fn index(&self, index: &[Idx]) -> &T {
+        return self.find(index).expect("TreeMap has no value at the given index");
     }
+}
+
+
+
+/*
+Subtree Views
+*/
 
-}
\ No newline at end of file
+// SubTree<'a, T, Idx, S>
+//
+// A readonly view of the nodes underneath a prefix of a TreeMap
+// This is created by TreeMap::subtree and doesn't copy any of the tree
+// The node at the prefix acts as the root of the view, so an empty index refers to the prefix itself
+pub struct SubTree<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    node: &'a TreeNode<T, Idx, S>
+}
+
+// SubTreeMut<'a, T, Idx, S>
+//
+// Mutable counterpart of SubTree
+// Values can be modified and inserted but not removed, as the view can't prune the nodes above it
+pub struct SubTreeMut<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    node: &'a mut TreeNode<T, Idx, S>,
+
+    // The value count of the map, which is updated by insert
+    len: &'a mut usize
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> SubTree<'a, T, Idx, S> {
+    // Finds a value relative to the prefix of the view
+    pub fn find<'q, Q, I>(&self, index: I) -> Option<&'a T>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        return self.node.find(index);
+    }
+
+    // Returns a view of a deeper prefix
+    pub fn subtree<'q, Q, I>(&self, prefix: I) -> Option<SubTree<'a, T, Idx, S>>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        let node = self.node.find_descendant(prefix)?;
+
+        return Some(SubTree { node });
+    }
+
+    // Depth-first iterator over the (index, value) pairs of the view
+    pub fn iter(&self) -> Iter<'a, T, Idx, S> {
+        return Iter::new(self.node);
+    }
+
+    // Breadth-first iterator over the (index, value) pairs of the view
+    pub fn iter_breadth_first(&self) -> BreadthFirstIter<'a, T, Idx, S> {
+        return BreadthFirstIter::new(self.node);
+    }
+
+    // Iterator over the relative indices of the view
+    pub fn keys(&self) -> Keys<'a, T, Idx, S> {
+        return Keys { inner: self.iter() };
+    }
+
+    // Iterator over the values of the view
+    pub fn values(&self) -> Values<'a, T, Idx, S> {
+        return Values::new(self.node);
+    }
+
+    // Returns the number of values in the view
+    // This walks the whole subtree
+    pub fn len(&self) -> usize {
+        return self.node.count();
+    }
+
+    // Returns true if the view holds no values
+    pub fn is_empty(&self) -> bool {
+        return self.values().next().is_none();
+    }
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> SubTreeMut<'a, T, Idx, S> {
+    // Finds a value relative to the prefix of the view
+    pub fn find<'q, Q, I>(&self, index: I) -> Option<&T>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        return self.node.find(index);
+    }
+
+    // Mutable counterpart of find
+    pub fn find_mut<'q, Q, I>(&mut self, index: I) -> Option<&mut T>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        return self.node.find_mut(index);
+    }
+
+    // Inserts a value relative to the prefix of the view
+    // If the value already exists it is overwritten and the old value is returned
+    pub fn insert(&mut self, index: &[Idx], value: T) -> Option<T> {
+        let old = self.node.insert(index, value);
+        if old.is_none() { *self.len += 1; }
+
+        return old;
+    }
+
+    // Returns a readonly view of a deeper prefix
+    pub fn subtree<'q, Q, I>(&self, prefix: I) -> Option<SubTree<'_, T, Idx, S>>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        let node = self.node.find_descendant(prefix)?;
+
+        return Some(SubTree { node });
+    }
+
+    // Returns a mutable view of a deeper prefix
+    pub fn subtree_mut<'q, Q, I>(&mut self, prefix: I) -> Option<SubTreeMut<'_, T, Idx, S>>
+    where Q: ?Sized + 'q, I: IntoIterator<Item = &'q Q>, S::Children: Lookup<Q, T, Idx, S> {
+        let node = self.node.find_descendant_mut(prefix)?;
+
+        return Some(SubTreeMut { node, len: &mut *self.len });
+    }
+
+    // Depth-first iterator over the (index, value) pairs of the view
+    pub fn iter(&self) -> Iter<'_, T, Idx, S> {
+        return Iter::new(self.node);
+    }
+
+    // Mutable counterpart of iter
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, Idx, S> {
+        return IterMut::new(self.node);
+    }
+
+    // Converts the view into a mutable iterator bound to the lifetime of the map
+    pub fn into_iter_mut(self) -> IterMut<'a, T, Idx, S> {
+        return IterMut::new(self.node);
+    }
+
+    // Iterator over the values of the view
+    pub fn values(&self) -> Values<'_, T, Idx, S> {
+        return Values::new(self.node);
+    }
+
+    // Returns the number of values in the view
+    // This walks the whole subtree
+    pub fn len(&self) -> usize {
+        return self.node.count();
+    }
+
+    // Returns true if the view holds no values
+    pub fn is_empty(&self) -> bool {
+        return self.values().next().is_none();
+    }
+}
+
+
+
+/*
+Iterators
+*/
+
+// Nodes waiting on an iterator stack are stored with their depth in the tree and their key
+// The root node is the only node without a key
+type StackEntry<'a, T, Idx, S> = (usize, Option<&'a Idx>, &'a TreeNode<T, Idx, S>);
+type StackEntryMut<'a, T, Idx, S> = (usize, Option<&'a Idx>, &'a mut TreeNode<T, Idx, S>);
+type StackEntryOwned<T, Idx, S> = (usize, Option<Idx>, TreeNode<T, Idx, S>);
+
+// Nodes waiting on the breadth-first queue are stored with their full index
+type QueueEntry<'a, T, Idx, S> = (Vec<Idx>, &'a TreeNode<T, Idx, S>);
+
+// Iter<'a, T, Idx, S>
+//
+// Depth-first iterator over the values of a tree
+// The full index of each value is rebuilt from the keys of the nodes above it
+pub struct Iter<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    // Nodes that are yet to be visited
+    stack: Vec<StackEntry<'a, T, Idx, S>>,
+
+    // The index of the last visited node
+    path: Vec<Idx>
+}
+
+// BreadthFirstIter<'a, T, Idx, S>
+//
+// Breadth-first iterator over the values of a tree
+// Every queued node holds its own index as siblings can't share a path
+pub struct BreadthFirstIter<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    queue: VecDeque<QueueEntry<'a, T, Idx, S>>
+}
+
+// IterMut<'a, T, Idx, S>
+//
+// Mutable counterpart of Iter
+pub struct IterMut<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    stack: Vec<StackEntryMut<'a, T, Idx, S>>,
+    path: Vec<Idx>
+}
+
+// IntoIter<T, Idx, S>
+//
+// Consuming depth-first iterator over the values of a tree
+pub struct IntoIter<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    stack: Vec<StackEntryOwned<T, Idx, S>>,
+    path: Vec<Idx>
+}
+
+// Iterator over the indices of a tree
+pub struct Keys<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    inner: Iter<'a, T, Idx, S>
+}
+
+// Iterator over the values of a tree
+// This doesn't rebuild the indices so is cheaper than Iter
+pub struct Values<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    stack: Vec<&'a TreeNode<T, Idx, S>>
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Iter<'a, T, Idx, S> {
+    // Creates a new iterator with the root node at the front of the stack
+    // Paths are relative to the root node
+    fn new(root: &'a TreeNode<T, Idx, S>) -> Self {
+        Iter {
+            stack: vec!((0, None, root)),
+            path: vec!()
+        }
+    }
+}
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Iterator for Iter<'a, T, Idx, S> {
+    type Item = (Vec<Idx>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (depth, key, node) = self.stack.pop()?;
+
+            // Rebuild the path to this node
+            // The root node has no key so is skipped
+            if let Some(key) = key {
+                self.path.truncate(depth - 1);
+                self.path.push(key.clone());
+            }
+
+            // Push sub-nodes in reverse so they are popped in storage order
+            for (key, child) in node.nodes.iter().rev() {
+                self.stack.push((depth + 1, Some(key), child));
+            }
+
+            if let Some(value) = node.value.as_ref() {
+                return Some((self.path.clone(), value));
+            }
+        }
+    }
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> BreadthFirstIter<'a, T, Idx, S> {
+    fn new(root: &'a TreeNode<T, Idx, S>) -> Self {
+        let mut queue = VecDeque::new();
+        queue.push_back((vec!(), root));
+
+        BreadthFirstIter { queue }
+    }
+}
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Iterator for BreadthFirstIter<'a, T, Idx, S> {
+    type Item = (Vec<Idx>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, node) = self.queue.pop_front()?;
+
+            for (key, child) in node.nodes.iter() {
+                let mut child_path = path.clone();
+                child_path.push(key.clone());
+
+                self.queue.push_back((child_path, child));
+            }
+
+            if let Some(value) = node.value.as_ref() {
+                return Some((path, value));
+            }
+        }
+    }
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> IterMut<'a, T, Idx, S> {
+    fn new(root: &'a mut TreeNode<T, Idx, S>) -> Self {
+        IterMut {
+            stack: vec!((0, None, root)),
+            path: vec!()
+        }
+    }
+}
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Iterator for IterMut<'a, T, Idx, S> {
+    type Item = (Vec<Idx>, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (depth, key, node) = self.stack.pop()?;
+
+            // Split the node so that the value and sub-nodes can be borrowed separately
+            let TreeNode { value, nodes } = node;
+
+            if let Some(key) = key {
+                self.path.truncate(depth - 1);
+                self.path.push(key.clone());
+            }
+
+            for (key, child) in nodes.iter_mut().rev() {
+                self.stack.push((depth + 1, Some(key), child));
+            }
+
+            if let Some(value) = value.as_mut() {
+                return Some((self.path.clone(), value));
+            }
+        }
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Iterator for IntoIter<T, Idx, S> {
+    type Item = (Vec<Idx>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (depth, key, node) = self.stack.pop()?;
+            let TreeNode { value, nodes } = node;
+
+            if let Some(key) = key {
+                self.path.truncate(depth - 1);
+                self.path.push(key);
+            }
+
+            for (key, child) in nodes.into_entries().rev() {
+                self.stack.push((depth + 1, Some(key), child));
+            }
+
+            if let Some(value) = value {
+                return Some((self.path.clone(), value));
+            }
+        }
+    }
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Iterator for Keys<'a, T, Idx, S> {
+    type Item = Vec<Idx>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return self.inner.next().map(|(path, _)| path);
+    }
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Values<'a, T, Idx, S> {
+    fn new(root: &'a TreeNode<T, Idx, S>) -> Self {
+        Values { stack: vec!(root) }
+    }
+}
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Iterator for Values<'a, T, Idx, S> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.stack.pop()?;
+            self.stack.extend(node.nodes.iter().rev().map(|(_, child)| child));
+
+            if let Some(value) = node.value.as_ref() {
+                return Some(value);
+            }
+        }
+    }
+}
+
+
+impl<T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> IntoIterator for TreeMap<T, Idx, S> {
+    type Item = (Vec<Idx>, T);
+    type IntoIter = IntoIter<T, Idx, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            stack: vec!((0, None, self.head)),
+            path: vec!()
+        }
+    }
+}
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> IntoIterator for &'a TreeMap<T, Idx, S> {
+    type Item = (Vec<Idx>, &'a T);
+    type IntoIter = Iter<'a, T, Idx, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.iter();
+    }
+}
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> IntoIterator for &'a mut TreeMap<T, Idx, S> {
+    type Item = (Vec<Idx>, &'a mut T);
+    type IntoIter = IterMut<'a, T, Idx, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.iter_mut();
+    }
+}
+
+
+
+// Range<'a, T, Idx>
+//
+// Iterator over a range of sibling keys in an ordered TreeMap
+// This is created by TreeMap::range
+pub struct Range<'a, T, Idx: Ord + Clone> {
+    inner: std::slice::Iter<'a, (Idx, TreeNode<T, Idx, Sorted>)>
+}
+impl<'a, T, Idx: Ord + Clone> Iterator for Range<'a, T, Idx> {
+    type Item = (&'a Idx, SubTree<'a, T, Idx, Sorted>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return self.inner.next().map(|(key, node)| (key, SubTree { node }));
+    }
+}
+impl<'a, T, Idx: Ord + Clone> DoubleEndedIterator for Range<'a, T, Idx> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        return self.inner.next_back().map(|(key, node)| (key, SubTree { node }));
+    }
+}
+
+
+
+/*
+Entry API
+*/
+
+// Entry<'a, T, Idx, S>
+//
+// A view into a single index of a TreeMap which may or may not hold a value
+// This is created by TreeMap::entry and follows the style of std::collections::btree_map::Entry
+pub enum Entry<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    Occupied(OccupiedEntry<'a, T, Idx, S>),
+    Vacant(VacantEntry<'a, T, Idx, S>)
+}
+
+// An entry where the index already holds a value
+pub struct OccupiedEntry<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    // The node that holds the value
+    node: &'a mut TreeNode<T, Idx, S>
+}
+
+// An entry where the index doesn't hold a value
+pub struct VacantEntry<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx> = Unsorted> {
+    // The deepest node that exists along the index
+    node: &'a mut TreeNode<T, Idx, S>,
+
+    // The value count of the map, which is incremented on insert
+    len: &'a mut usize,
+
+    // The keys of the index that are below node
+    // These are created when a value is inserted
+    remaining: Vec<Idx>
+}
+
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> Entry<'a, T, Idx, S> {
+    // Inserts the default if the entry is vacant
+    // Returns a mutable reference to the value in the entry
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default)
+        }
+    }
+
+    // Same as or_insert but the default is only computed if the entry is vacant
+    pub fn or_insert_with<F: FnOnce() -> T>(self, default: F) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default())
+        }
+    }
+
+    // Same as or_insert but uses T::default
+    pub fn or_default(self) -> &'a mut T where T: Default {
+        return self.or_insert_with(T::default);
+    }
+
+    // Calls f on the value if the entry is occupied
+    // The entry is returned so that further calls can be chained
+    pub fn and_modify<F: FnOnce(&mut T)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(entry) => {
+                f(entry.node.value.as_mut().unwrap());
+                Entry::Occupied(entry)
+            },
+            Entry::Vacant(entry) => Entry::Vacant(entry)
+        }
+    }
+}
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> OccupiedEntry<'a, T, Idx, S> {
+    // Returns a reference to the value in the entry
+    pub fn get(&self) -> &T {
+        return self.node.value.as_ref().unwrap();
+    }
+
+    // Returns a mutable reference to the value in the entry
+    pub fn get_mut(&mut self) -> &mut T {
+        return self.node.value.as_mut().unwrap();
+    }
+
+    // Converts the entry into a mutable reference bound to the lifetime of the map
+    pub fn into_mut(self) -> &'a mut T {
+        return self.node.value.as_mut().unwrap();
+    }
+
+    // Replaces the value in the entry and returns the old value
+    pub fn insert(&mut self, value: T) -> T {
+        return std::mem::replace(self.get_mut(), value);
+    }
+}
+
+impl<'a, T, Idx: PartialEq + Clone, S: ChildStorage<T, Idx>> VacantEntry<'a, T, Idx, S> {
+    // Inserts a value into the entry, creating any missing nodes along the way
+    // Returns a mutable reference to the inserted value
+    pub fn insert(self, value: T) -> &'a mut T {
+        let mut node = self.node;
+
+        // Create the remaining nodes
+        for key in self.remaining {
+            node = node.nodes.insert(key, TreeNode::new());
+        }
+
+        *self.len += 1;
+
+        return node.value.insert(value);
+    }
+}
+
+
+
+
+/*
+Tests:
+    - TreeMap::insert
+    - TreeMap::find     On valid target (Return Some)
+    - TreeMap::find     On invalid target (Return None)
+    - TreeMap::remove
+    - TreeMap::remove   Pruning of emptied nodes
+    - TreeMap::remove_prefix
+    - TreeMap::find_mut
+    - TreeMap::entry    Occupied and Vacant entries
+    - TreeMap::entry    Each level is looked up once
+    - TreeMap::iter     Depth-first and breadth-first ordering
+    - TreeMap::iter_mut
+    - TreeMap::into_iter
+    - TreeMap::keys / TreeMap::values
+    - TreeMap::subtree
+    - TreeMap::subtree_mut
+    - TreeMap::longest_prefix
+    - TreeMap::find_pattern
+    - TreeMap::range    Ordered sibling ranges
+    - TreeMap::first / TreeMap::last
+    - TreeMap::last     After remove_current, retain and filter_map_values
+    - TreeMap::merge    Grafting and conflict resolution
+    - TreeMap::union
+    - TreeMap::retain   Filtering and pruning
+    - TreeMap::map_values / TreeMap::filter_map_values
+    - TreeMap::len      Counter kept through every mutation
+    - Standard traits   FromIterator, Extend, Clone, PartialEq, Debug and Index
+    - TreeMap::insert_path / find_path / remove_path / iter_paths
+    - Borrowed lookups  Finding String keys by &str with every storage strategy
+    - Empty index       The empty index as a real key, with a key type that has no Default
+
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    Test: Insert
+
+    Summary:
+        This test is to ensure that TreeMAp<i32, &str> can correctly insert elements without panicing.
+        It does not check that the elemnt has been inserted correctly
+     */
+    #[test]
+    fn insert() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+
+        // Check insert
+        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+
+        // Check insert along created path
+        map.insert(vec!["a", "b", "d"].as_slice(), 1);
+
+        // Insert along new path
+        map.insert(vec!["e", "f"].as_slice(), 1);
+
+        // Long insert
+        map.insert(vec!["e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p"].as_slice(), 1);
+    }
+
+
+    /*
+    Test: Find
+
+    Summary:
+        Validates that TreeMap can correctly read back inserted elements
+     */
+    #[test]
+    fn find() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+
+        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+        map.insert(vec!["a", "b", "d"].as_slice(), 2);
+        map.insert(vec!["e", "f"].as_slice(), 3);
+
+
+        // Check response
+        assert_eq!(*map.find(vec!["a", "b", "c"].as_slice()).unwrap(), 1);
+        assert_eq!(*map.find(vec!["a", "b", "d"].as_slice()).unwrap(), 2);
+        assert_eq!(*map.find(vec!["e", "f"].as_slice()).unwrap(), 3);
+    }
+
+
+    /*
+    Test: Find Invalid items
+
+    Summary:
+        Validates that TreeMap will error correctly when find is called on an unknown index
+     */
+    #[test]
+    fn find_invalid() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+
+        // Wrong from root
+        assert_eq!(map.find(vec!["e", "f"].as_slice()).is_none(), true);
+
+        // Wrong along index
+        assert_eq!(map.find(vec!["a", "b", "z"].as_slice()).is_none(), true);
+    }
+
+
+    /*
+    Test: Remove
+
+    Summary:
+        Validates that removed values are returned and can no longer be found
+        Values on other paths must be left untouched
+     */
+    #[test]
+    fn remove() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+        map.insert(vec!["a", "b"].as_slice(), 2);
+        map.insert(vec!["e", "f"].as_slice(), 3);
+
+        assert_eq!(map.remove(vec!["a", "b", "c"].as_slice()), Some(1));
+        assert!(map.find(vec!["a", "b", "c"].as_slice()).is_none());
+
+        // Removing twice or removing an unknown index returns None
+        assert!(map.remove(vec!["a", "b", "c"].as_slice()).is_none());
+        assert!(map.remove(vec!["x"].as_slice()).is_none());
+
+        // Other values remain
+        assert_eq!(*map.find(vec!["a", "b"].as_slice()).unwrap(), 2);
+        assert_eq!(*map.find(vec!["e", "f"].as_slice()).unwrap(), 3);
+    }
+
+
+    /*
+    Test: Remove Pruning
+
+    Summary:
+        Checks that intermediate nodes without values or children are removed from the tree
+        while nodes that still hold a value are kept
+     */
+    #[test]
+    fn remove_prune() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a", "b", "c", "d"].as_slice(), 1);
+        map.insert(vec!["a"].as_slice(), 2);
+        map.insert(vec!["e"].as_slice(), 3);
+
+        map.remove(vec!["a", "b", "c", "d"].as_slice());
+
+        // "b", "c" and "d" are pruned but "a" still holds a value
+        let a = map.head.find_node(&"a").unwrap();
+        assert_eq!(a.nodes.len(), 0);
+        assert_eq!(a.value, Some(2));
+
+        // Removing "a" and "e" leaves an empty root
+        map.remove(vec!["a"].as_slice());
+        map.remove(vec!["e"].as_slice());
+        assert!(map.head.is_empty());
+    }
+
+
+    /*
+    Test: Remove Prefix
+
+    Summary:
+        Checks that a whole subtree is detached and returned with paths relative to the prefix
+     */
+    #[test]
+    fn remove_prefix() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+        map.insert(vec!["a", "b", "d"].as_slice(), 2);
+        map.insert(vec!["e"].as_slice(), 3);
+
+        let sub = map.remove_prefix(vec!["a", "b"].as_slice()).unwrap();
+
+        // The subtree is relative to the prefix
+        assert_eq!(*sub.find(vec!["c"].as_slice()).unwrap(), 1);
+        assert_eq!(*sub.find(vec!["d"].as_slice()).unwrap(), 2);
+
+        // The prefix and the emptied "a" node are gone
+        assert!(map.find(vec!["a", "b", "c"].as_slice()).is_none());
+        assert!(map.head.find_node(&"a").is_none());
+        assert_eq!(*map.find(vec!["e"].as_slice()).unwrap(), 3);
+
+        // Unknown prefix
+        assert!(map.remove_prefix(vec!["x"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Find Mut
+
+    Summary:
+        Validates that values can be modified in place through find_mut
+     */
+    #[test]
+    fn find_mut() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a", "b"].as_slice(), 1);
+
+        *map.find_mut(vec!["a", "b"].as_slice()).unwrap() += 10;
+        assert_eq!(*map.find(vec!["a", "b"].as_slice()).unwrap(), 11);
+
+        // Intermediate nodes and unknown indices hold no value
+        assert!(map.find_mut(vec!["a"].as_slice()).is_none());
+        assert!(map.find_mut(vec!["a", "c"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Entry
+
+    Summary:
+        Checks that entries are created when vacant and modified when occupied
+     */
+    #[test]
+    fn entry() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a"].as_slice(), 5);
+
+        // Vacant entry below an existing node
+        *map.entry(vec!["a", "b", "c"].as_slice()).or_insert(0) += 1;
+        assert_eq!(*map.find(vec!["a", "b", "c"].as_slice()).unwrap(), 1);
+
+        // Occupied entry
+        map.entry(vec!["a", "b", "c"].as_slice()).and_modify(|v| *v += 1).or_insert(0);
+        assert_eq!(*map.find(vec!["a", "b", "c"].as_slice()).unwrap(), 2);
+
+        // Vacant intermediate node
+        assert_eq!(*map.entry(vec!["a", "b"].as_slice()).or_insert_with(|| 7), 7);
+
+        match map.entry(vec!["a"].as_slice()) {
+            Entry::Occupied(mut entry) => { assert_eq!(entry.insert(6), 5); },
+            Entry::Vacant(_) => { panic!("Expected occupied entry"); }
+        };
+        assert_eq!(*map.find(vec!["a"].as_slice()).unwrap(), 6);
+
+        // The existing "a" node was reused rather than duplicated
+        assert_eq!(map.head.nodes.len(), 1);
+    }
+
+
+    /*
+    Test: Entry single walk
+
+    Summary:
+        Checks that entry compares each key along the index only once by counting key comparisons
+     */
+    #[test]
+    fn entry_single_walk() {
+        thread_local! {
+            static COMPARISONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+        }
+
+        // A key that counts how many times it is compared
+        #[derive(Clone, Debug)]
+        struct Key(&'static str);
+        impl PartialEq for Key {
+            fn eq(&self, other: &Self) -> bool {
+                COMPARISONS.with(|count| count.set(count.get() + 1));
+                return self.0 == other.0;
+            }
+        }
+
+        let mut map: TreeMap<i32, Key> = TreeMap::new();
+        map.insert(&[Key("a"), Key("b"), Key("c")], 1);
+
+        // Occupied entry three levels down
+        COMPARISONS.with(|count| count.set(0));
+        *map.entry(&[Key("a"), Key("b"), Key("c")]).or_insert(0) += 1;
+        assert_eq!(COMPARISONS.with(|count| count.get()), 3);
+
+        // Vacant entry that stops at the third level
+        COMPARISONS.with(|count| count.set(0));
+        assert!(matches!(map.entry(&[Key("a"), Key("b"), Key("d")]), Entry::Vacant(_)));
+        assert_eq!(COMPARISONS.with(|count| count.get()), 3);
+
+        assert_eq!(*map.find(&[Key("a"), Key("b"), Key("c")]).unwrap(), 2);
+    }
+
+
+    // Helper function for building the tree used by the iterator tests
+    fn iter_map() -> TreeMap<i32, &'static str> {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+        map.insert(vec!["a"].as_slice(), 2);
+        map.insert(vec!["e"].as_slice(), 3);
+        map.insert(vec!["a", "d"].as_slice(), 4);
+
+        return map;
+    }
+
+
+    /*
+    Test: Iter
+
+    Summary:
+        Checks that the depth-first and breadth-first iterators visit every value exactly once
+        in the expected order and with the correct full index
+     */
+    #[test]
+    fn iter() {
+        let map = iter_map();
+
+        let depth_first: Vec<(Vec<&str>, &i32)> = map.iter().collect();
+        assert_eq!(depth_first, vec!(
+            (vec!["a"], &2),
+            (vec!["a", "b", "c"], &1),
+            (vec!["a", "d"], &4),
+            (vec!["e"], &3)
+        ));
+
+        let breadth_first: Vec<(Vec<&str>, &i32)> = map.iter_breadth_first().collect();
+        assert_eq!(breadth_first, vec!(
+            (vec!["a"], &2),
+            (vec!["e"], &3),
+            (vec!["a", "d"], &4),
+            (vec!["a", "b", "c"], &1)
+        ));
+
+        // An empty map yields nothing
+        let empty: TreeMap<i32, &str> = TreeMap::new();
+        assert!(empty.iter().next().is_none());
+        assert!(empty.iter_breadth_first().next().is_none());
+    }
+
+
+    /*
+    Test: Iter Mut
+
+    Summary:
+        Validates that values can be modified through iter_mut
+     */
+    #[test]
+    fn iter_mut() {
+        let mut map = iter_map();
+
+        for (path, value) in map.iter_mut() {
+            *value += path.len() as i32 * 10;
+        }
+
+        assert_eq!(*map.find(vec!["a", "b", "c"].as_slice()).unwrap(), 31);
+        assert_eq!(*map.find(vec!["a"].as_slice()).unwrap(), 12);
+        assert_eq!(*map.find(vec!["a", "d"].as_slice()).unwrap(), 24);
+    }
+
+
+    /*
+    Test: Into Iter
+
+    Summary:
+        Checks that the consuming iterator yields owned values in depth-first order
+     */
+    #[test]
+    fn into_iter() {
+        let map = iter_map();
+
+        let items: Vec<(Vec<&str>, i32)> = map.into_iter().collect();
+        assert_eq!(items, vec!(
+            (vec!["a"], 2),
+            (vec!["a", "b", "c"], 1),
+            (vec!["a", "d"], 4),
+            (vec!["e"], 3)
+        ));
+    }
+
+
+    /*
+    Test: Keys and Values
+
+    Summary:
+        Checks that keys and values match the items of iter
+     */
+    #[test]
+    fn keys_values() {
+        let map = iter_map();
+
+        let keys: Vec<Vec<&str>> = map.keys().collect();
+        assert_eq!(keys, vec!(vec!["a"], vec!["a", "b", "c"], vec!["a", "d"], vec!["e"]));
+
+        let values: Vec<&i32> = map.values().collect();
+        assert_eq!(values, vec!(&2, &1, &4, &3));
+    }
+
+
+    /*
+    Test: Subtree
+
+    Summary:
+        Checks that a subtree view finds and iterates values relative to its prefix
+     */
+    #[test]
+    fn subtree() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["services", "auth", "port"].as_slice(), 1);
+        map.insert(vec!["services", "auth", "tls", "enabled"].as_slice(), 2);
+        map.insert(vec!["services", "db", "port"].as_slice(), 3);
+
+        let auth = map.subtree(vec!["services", "auth"].as_slice()).unwrap();
+
+        assert_eq!(*auth.find(vec!["port"].as_slice()).unwrap(), 1);
+        assert!(auth.find(vec!["db", "port"].as_slice()).is_none());
+        assert_eq!(auth.len(), 2);
+
+        let items: Vec<(Vec<&str>, &i32)> = auth.iter().collect();
+        assert_eq!(items, vec!((vec!["port"], &1), (vec!["tls", "enabled"], &2)));
+
+        // Nested views
+        let tls = auth.subtree(vec!["tls"].as_slice()).unwrap();
+        assert_eq!(*tls.find(vec!["enabled"].as_slice()).unwrap(), 2);
+
+        // Unknown prefix
+        assert!(map.subtree(vec!["services", "cache"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Subtree Mut
+
+    Summary:
+        Validates that values can be modified and inserted through a mutable subtree view
+     */
+    #[test]
+    fn subtree_mut() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["a", "b", "c"].as_slice(), 1);
+        map.insert(vec!["e"].as_slice(), 2);
+
+        {
+            let mut view = map.subtree_mut(vec!["a"].as_slice()).unwrap();
+            *view.find_mut(vec!["b", "c"].as_slice()).unwrap() = 10;
+            view.insert(vec!["d"].as_slice(), 3);
+
+            for (_, value) in view.iter_mut() {
+                *value += 1;
+            }
+
+            assert_eq!(view.len(), 2);
+        }
+
+        assert_eq!(*map.find(vec!["a", "b", "c"].as_slice()).unwrap(), 11);
+        assert_eq!(*map.find(vec!["a", "d"].as_slice()).unwrap(), 4);
+        assert_eq!(*map.find(vec!["e"].as_slice()).unwrap(), 2);
+    }
+
+
+    /*
+    Test: Longest Prefix
+
+    Summary:
+        Checks that the deepest value along an index is returned with the matched depth
+        even when the full index doesn't exist
+     */
+    #[test]
+    fn longest_prefix() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["10"].as_slice(), 1);
+        map.insert(vec!["10", "0", "1"].as_slice(), 2);
+        map.insert(vec!["192", "168"].as_slice(), 3);
+
+        // Exact match
+        assert_eq!(map.longest_prefix(vec!["10", "0", "1"].as_slice()), Some((3, &2)));
+
+        // Ancestor holds the value
+        assert_eq!(map.longest_prefix(vec!["10", "0", "2", "5"].as_slice()), Some((1, &1)));
+        assert_eq!(map.longest_prefix(vec!["10", "0"].as_slice()), Some((1, &1)));
+        assert_eq!(map.longest_prefix(vec!["192", "168", "0", "1"].as_slice()), Some((2, &3)));
+
+        // No value along the index
+        assert!(map.longest_prefix(vec!["192"].as_slice()).is_none());
+        assert!(map.longest_prefix(vec!["172", "16"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Find Pattern
+
+    Summary:
+        Checks that single and multi level wildcards match the expected values
+     */
+    #[test]
+    fn find_pattern() {
+        use PatternSegment::*;
+
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        map.insert(vec!["sport", "tennis", "player1"].as_slice(), 1);
+        map.insert(vec!["sport", "tennis", "player2"].as_slice(), 2);
+        map.insert(vec!["sport", "golf", "player1"].as_slice(), 3);
+        map.insert(vec!["sport"].as_slice(), 4);
+        map.insert(vec!["news"].as_slice(), 5);
+
+        // Literal keys only
+        assert_eq!(map.find_pattern(&[Key("sport"), Key("golf"), Key("player1")]), vec!((vec!["sport", "golf", "player1"], &3)));
+
+        // Single level wildcard
+        assert_eq!(map.find_pattern(&[Key("sport"), Single, Key("player1")]), vec!(
+            (vec!["sport", "tennis", "player1"], &1),
+            (vec!["sport", "golf", "player1"], &3)
+        ));
+        assert_eq!(map.find_pattern(&[Single]), vec!((vec!["sport"], &4), (vec!["news"], &5)));
+
+        // Multi level wildcard includes the parent level
+        assert_eq!(map.find_pattern(&[Key("sport"), Key("tennis"), Multi]), vec!(
+            (vec!["sport", "tennis", "player1"], &1),
+            (vec!["sport", "tennis", "player2"], &2)
+        ));
+        assert_eq!(map.find_pattern(&[Key("sport"), Multi]).len(), 4);
+
+        // No matches
+        assert!(map.find_pattern(&[Key("sport"), Single, Key("player3")]).is_empty());
+    }
+
+
+    /*
+    Test: Find Pattern Invalid
+
+    Summary:
+        A multi level wildcard that isn't the last segment should panic
+     */
+    #[test]
+    #[should_panic]
+    fn find_pattern_invalid() {
+        let map: TreeMap<i32, &str> = TreeMap::new();
+
+        map.find_pattern(&[PatternSegment::Multi, PatternSegment::Key("a")]);
+    }
+
+
+    // Helper function for building the ordered tree used by the range tests
+    fn metrics_map() -> TreeMap<i32, i32, Sorted> {
+        let mut map: TreeMap<i32, i32, Sorted> = TreeMap::new();
+
+        // Inserted out of order
+        for day in [12, 3, 7, 1, 10, 5, 9] {
+            map.insert(vec![2024, 3, day].as_slice(), day * 100);
+        }
+        map.insert(vec![2024, 2, 28].as_slice(), 1);
+        map.insert(vec![2024, 4, 1].as_slice(), 2);
+
+        return map;
+    }
+
+
+    /*
+    Test: Range
+
+    Summary:
+        Checks that only the sub-nodes with a key in the range are returned, in key order
+     */
+    #[test]
+    fn range() {
+        let map = metrics_map();
+
+        let days: Vec<(i32, i32)> = map.range(vec![2024, 3].as_slice(), 3..10)
+            .map(|(day, node)| (*day, *node.find(&[]).unwrap()))
+            .collect();
+        assert_eq!(days, vec!((3, 300), (5, 500), (7, 700), (9, 900)));
+
+        // Inclusive and unbounded ranges
+        assert_eq!(map.range(vec![2024, 3].as_slice(), 10..=12).count(), 2);
+        assert_eq!(map.range(vec![2024, 3].as_slice(), ..).count(), 7);
+        assert_eq!(map.range(vec![2024, 3].as_slice(), ..5).next_back().map(|(day, _)| *day), Some(3));
+
+        // Range of months, which holds deeper values
+        let months: Vec<i32> = map.range(vec![2024].as_slice(), 3..).map(|(month, node)| *month * 100 + node.len() as i32).collect();
+        assert_eq!(months, vec!(307, 401));
+
+        // Empty ranges and unknown prefixes
+        assert_eq!(map.range(vec![2024, 3].as_slice(), 13..20).count(), 0);
+        assert_eq!(map.range(vec![2025].as_slice(), ..).count(), 0);
+    }
+
+
+    /*
+    Test: First and Last
+
+    Summary:
+        Checks that first and last return the lexicographically smallest and largest index under a prefix
+        and that iteration of an ordered map is in lexicographic order
+     */
+    #[test]
+    fn first_last() {
+        let map = metrics_map();
+
+        assert_eq!(map.first(vec![2024, 3].as_slice()), Some((vec![2024, 3, 1], &100)));
+        assert_eq!(map.last(vec![2024, 3].as_slice()), Some((vec![2024, 3, 12], &1200)));
+        assert_eq!(map.first(&[]), Some((vec![2024, 2, 28], &1)));
+        assert_eq!(map.last(&[]), Some((vec![2024, 4, 1], &2)));
+        assert!(map.first(vec![2025].as_slice()).is_none());
+
+        let keys: Vec<Vec<i32>> = map.keys().collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+    }
+
+
+    /*
+    Test: Last After Removals
+
+    Summary:
+        Checks that last still finds the largest remaining index after the last branches
+        have been removed by a cursor, retain and filter_map_values
+     */
+    #[test]
+    fn last_after_removals() {
+        let mut map = metrics_map();
+        map.insert(vec![2024, 4].as_slice(), 3);
+
+        // Remove [2024, 4, 1], leaving [2024, 4] as the last value
+        let mut cursor = map.cursor_mut();
+        cursor.descend(&2024);
+        cursor.descend(&4);
+        cursor.descend(&1);
+        cursor.remove_current();
+        assert_eq!(map.last(&[]), Some((vec![2024, 4], &3)));
+
+        // Remove [2024, 4] and the largest days of March
+        map.retain(|index, _| index.len() == 3 && (index[1] != 3 || index[2] < 10));
+        assert_eq!(map.last(&[]), Some((vec![2024, 3, 9], &900)));
+        assert_eq!(map.last(vec![2024, 4].as_slice()), None);
+
+        // Remove all of March
+        let map = map.filter_map_values(|value| if value >= 100 { None } else { Some(value) });
+        assert_eq!(map.last(&[]), Some((vec![2024, 2, 28], &1)));
+        assert_eq!(map.last(vec![2024, 3].as_slice()), None);
+    }
+
+
+    /*
+    Test: Merge
+
+    Summary:
+        Checks that the other tree is grafted in and that conflicts are resolved with the correct index and values
+     */
+    #[test]
+    fn merge() {
+        let mut defaults: TreeMap<i32, &str> = TreeMap::new();
+        defaults.insert(vec!["log", "level"].as_slice(), 1);
+        defaults.insert(vec!["log", "file"].as_slice(), 2);
+        defaults.insert(vec!["port"].as_slice(), 3);
+
+        let mut user: TreeMap<i32, &str> = TreeMap::new();
+        user.insert(vec!["log", "level"].as_slice(), 10);
+        user.insert(vec!["log", "color", "enabled"].as_slice(), 20);
+        user.insert(vec!["theme"].as_slice(), 30);
+
+        let mut conflicts = vec!();
+        defaults.merge(user, |index, mine, theirs| {
+            conflicts.push((index.to_vec(), mine, theirs));
+            theirs
+        });
+
+        assert_eq!(conflicts, vec!((vec!["log", "level"], 1, 10)));
+
+        let items: Vec<(Vec<&str>, &i32)> = defaults.iter().collect();
+        assert_eq!(items, vec!(
+            (vec!["log", "level"], &10),
+            (vec!["log", "file"], &2),
+            (vec!["log", "color", "enabled"], &20),
+            (vec!["port"], &3),
+            (vec!["theme"], &30)
+        ));
+    }
+
+
+    /*
+    Test: Union
+
+    Summary:
+        Checks that union combines both trees without modifying either
+     */
+    #[test]
+    fn union() {
+        let mut a: TreeMap<i32, &str> = TreeMap::new();
+        a.insert(vec!["x", "y"].as_slice(), 1);
+        a.insert(vec!["z"].as_slice(), 2);
+
+        let mut b: TreeMap<i32, &str> = TreeMap::new();
+        b.insert(vec!["x", "y"].as_slice(), 10);
+        b.insert(vec!["x", "w"].as_slice(), 20);
+
+        // resolve is only called where both trees hold a value
+        let mut conflicts = vec!();
+        let c = a.union(&b, |index, mine, theirs| {
+            conflicts.push(index.to_vec());
+            mine + theirs
+        });
+
+        assert_eq!(conflicts, vec!(vec!["x", "y"]));
+        assert_eq!(c.len(), 3);
+        assert_eq!(*c.find(vec!["x", "y"].as_slice()).unwrap(), 11);
+        assert_eq!(*c.find(vec!["x", "w"].as_slice()).unwrap(), 20);
+        assert_eq!(*c.find(vec!["z"].as_slice()).unwrap(), 2);
+
+        // Originals are untouched
+        assert_eq!(*a.find(vec!["x", "y"].as_slice()).unwrap(), 1);
+        assert!(a.find(vec!["x", "w"].as_slice()).is_none());
+        assert_eq!(*b.find(vec!["x", "y"].as_slice()).unwrap(), 10);
+    }
+
+
+    /*
+    Test: Retain
+
+    Summary:
+        Checks that values failing the predicate are removed and emptied branches are pruned
+     */
+    #[test]
+    fn retain() {
+        let mut map = iter_map();
+
+        let mut visited = vec!();
+        map.retain(|index, value| {
+            visited.push(index.to_vec());
+            *value += 1;
+
+            *value % 2 == 1
+        });
+
+        // Every value is visited in depth-first order
+        assert_eq!(visited, vec!(vec!["a"], vec!["a", "b", "c"], vec!["a", "d"], vec!["e"]));
+
+        // Values were modified and only odd values remain
+        let items: Vec<(Vec<&str>, &i32)> = map.iter().collect();
+        assert_eq!(items, vec!((vec!["a"], &3), (vec!["a", "d"], &5)));
+
+        // "b" was pruned along with "c"
+        assert!(map.subtree(vec!["a", "b"].as_slice()).is_none());
+        assert!(map.subtree(vec!["e"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Map Values
+
+    Summary:
+        Checks that map_values changes the value type and keeps the shape of the tree,
+        and that filter_map_values prunes removed values
+     */
+    #[test]
+    fn map_values() {
+        let map = iter_map();
+
+        let strings: TreeMap<String, &str> = map.map_values(|value| value.to_string());
+        assert_eq!(strings.find(vec!["a", "b", "c"].as_slice()).unwrap(), "1");
+        assert_eq!(strings.find(vec!["e"].as_slice()).unwrap(), "3");
+        assert_eq!(strings.keys().collect::<Vec<Vec<&str>>>(), iter_map().keys().collect::<Vec<Vec<&str>>>());
+
+        let parsed: TreeMap<u8, &str> = strings.filter_map_values(|value| if value == "1" { None } else { value.parse().ok() });
+        let items: Vec<(Vec<&str>, &u8)> = parsed.iter().collect();
+        assert_eq!(items, vec!((vec!["a"], &2), (vec!["a", "d"], &4), (vec!["e"], &3)));
+        assert!(parsed.subtree(vec!["a", "b"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Len
+
+    Summary:
+        Checks that the value counter is kept correct by every operation that adds or removes values
+     */
+    #[test]
+    fn len() {
+        let mut map: TreeMap<i32, &str> = TreeMap::new();
+        assert!(map.is_empty());
+
+        map.insert(vec!["a", "b"].as_slice(), 1);
+        map.insert(vec!["a", "b"].as_slice(), 2); // Overwrite
+        map.insert(vec!["a"].as_slice(), 3);
+        map.insert(vec!["c", "d", "e"].as_slice(), 4);
+        assert_eq!(map.len(), 3);
+
+        // Entry
+        *map.entry(vec!["f"].as_slice()).or_insert(0) += 1;
+        *map.entry(vec!["f"].as_slice()).or_insert(0) += 1;
+        assert_eq!(map.len(), 4);
+
+        // Subtree views and cursors
+        map.subtree_mut(vec!["c"].as_slice()).unwrap().insert(vec!["g"].as_slice(), 5);
+        {
+            let mut cursor = map.cursor_mut();
+            cursor.descend(&"c");
+            cursor.insert_child("h", 6);
+            cursor.set_value(7);
+        }
+        assert_eq!(map.len(), 7);
+
+        {
+            let mut cursor = map.cursor_mut();
+            cursor.descend(&"c");
+            cursor.remove_current();
+        }
+        assert_eq!(map.len(), 3);
+
+        // Removal
+        map.remove(vec!["a", "b"].as_slice());
+        map.remove(vec!["a", "b"].as_slice());
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.remove_prefix(vec!["a"].as_slice()).unwrap().len(), 1);
+        assert_eq!(map.len(), 1);
+
+        // Merge with one conflict
+        let other: TreeMap<i32, &str> = vec!((vec!["f"], 1), (vec!["x"], 2)).into_iter().collect();
+        map.merge(other, |_, mine, _| mine);
+        assert_eq!(map.len(), 2);
+
+        map.retain(|index, _| index[0] == "x");
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.iter().count(), map.len());
+
+        map.clear();
+        assert!(map.is_empty());
+        assert!(map.find(vec!["x"].as_slice()).is_none());
+    }
+
+
+    /*
+    Test: Standard Traits
+
+    Summary:
+        Checks collecting, extending, cloning, comparing, formatting and indexing a TreeMap
+     */
+    #[test]
+    fn standard_traits() {
+        let mut map: TreeMap<i32, &str> = vec!(
+            (vec!["a", "b"], 1),
+            (vec!["c"], 2)
+        ).into_iter().collect();
+
+        map.extend(vec!((vec!["a", "d"], 3)));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map[vec!["a", "d"].as_slice()], 3);
+
+        // Clones are equal and independent
+        let mut copy = map.clone();
+        assert_eq!(copy, map);
+        copy.insert(vec!["a", "b"].as_slice(), 10);
+        assert_ne!(copy, map);
+        assert_eq!(map[vec!["a", "b"].as_slice()], 1);
+
+        // Insertion order doesn't affect equality
+        let reordered: TreeMap<i32, &str> = vec!(
+            (vec!["c"], 2),
+            (vec!["a", "d"], 3),
+            (vec!["a", "b"], 1)
+        ).into_iter().collect();
+        assert_eq!(reordered, map);
+
+        assert_eq!(format!("{:?}", map), r#"{["a", "b"]: 1, ["a", "d"]: 3, ["c"]: 2}"#);
+        assert_eq!(TreeMap::<i32, &str>::default(), TreeMap::new());
+    }
+
+
+    /*
+    Test: Index Invalid
+
+    Summary:
+        Indexing an index without a value should panic
+     */
+    #[test]
+    #[should_panic]
+    fn index_invalid() {
+        let map: TreeMap<i32, &str> = TreeMap::new();
+
+        let _ = map[vec!["a"].as_slice()];
+    }
+
+
+    /*
+    Test: Paths
+
+    Summary:
+        Checks the delimited string path methods, including escaped separators
+     */
+    #[test]
+    fn paths() {
+        let mut map: TreeMap<i32, String> = TreeMap::new();
+
+        assert_eq!(map.insert_path("a/b/c", '/', 1), None);
+        assert_eq!(map.insert_path(r"a/b\/c", '/', 2), None);
+        assert_eq!(map.insert_path("a.d", '.', 3), None);
+
+        assert_eq!(map.find(&["a".to_string(), "b".to_string(), "c".to_string()]), Some(&1));
+        assert_eq!(map.find(&["a".to_string(), "b/c".to_string()]), Some(&2));
+        assert_eq!(map.find_path("a/d", '/'), Some(&3));
+        assert_eq!(map.find_path("a.b.c", '.'), Some(&1));
+        assert_eq!(map.find_path("a/b", '/'), None);
+
+        *map.find_path_mut("a/d", '/').unwrap() += 10;
+        let paths: Vec<(String, &i32)> = map.iter_paths('/').collect();
+        assert_eq!(paths, vec!(
+            ("a/b/c".to_string(), &1),
+            (r"a/b\/c".to_string(), &2),
+            ("a/d".to_string(), &13)
+        ));
+
+        assert_eq!(map.remove_path(r"a/b\/c", '/'), Some(2));
+        assert_eq!(map.remove_path(r"a/b\/c", '/'), None);
+        assert_eq!(map.len(), 2);
+    }
+
+
+    /*
+    Test: Borrowed Lookups
+
+    Summary:
+        Checks that lookups accept iterators of borrowed keys for every storage strategy
+     */
+    #[test]
+    fn borrowed_lookups() {
+        fn check<S: ChildStorage<i32, String>>() where S::Children: Lookup<str, i32, String, S> {
+            let mut map: TreeMap<i32, String, S> = TreeMap::new();
+            map.insert(&["a".to_string(), "b".to_string()], 1);
+            map.insert(&["a".to_string(), "c".to_string()], 2);
+
+            assert_eq!(map.find(["a", "b"]), Some(&1));
+            assert_eq!(map.find("a/c".split('/')), Some(&2));
+            assert_eq!(map.find(["a", "x"]), None);
+            assert_eq!(map.find([""; 0]), None);
+            assert_eq!(map.longest_prefix(["a", "b", "z"]), Some((2, &1)));
+
+            *map.find_mut(["a", "b"]).unwrap() += 10;
+            assert_eq!(map.subtree(["a"]).unwrap().find(["b"]), Some(&11));
+            *map.subtree_mut(["a"]).unwrap().find_mut(["c"]).unwrap() += 10;
+            assert_eq!(map.find(["a", "c"]), Some(&12));
+        }
+
+        check::<Unsorted>();
+        check::<Sorted>();
+        check::<crate::Hashed>();
+    }
+
+
+    /*
+    Test: Empty Index
+
+    Summary:
+        Checks that the empty index holds a value like any other key
+        The key type has no Default implementation
+     */
+    #[test]
+    fn empty_index() {
+        #[derive(Clone, Debug, PartialEq)]
+        enum Key { A, B }
+
+        let mut map: TreeMap<i32, Key> = TreeMap::new();
+        assert_eq!(map.insert(&[], 1), None);
+        assert_eq!(map.insert(&[Key::A, Key::B], 2), None);
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.find(&[]), Some(&1));
+        assert_eq!(map.longest_prefix(&[Key::B]), Some((0, &1)));
+        assert_eq!(map.find_pattern(&[]), vec!((vec!(), &1)));
+        assert_eq!(map.iter().next(), Some((vec!(), &1)));
+
+        *map.entry(&[]).or_insert(0) += 10;
+        assert_eq!(map.find(&[]), Some(&11));
+        assert_eq!(map.insert(&[], 3), Some(11));
+
+        let mut cursor = map.cursor_mut();
+        assert_eq!(cursor.set_value(4), Some(3));
+
+        assert_eq!(map.remove(&[]), Some(4));
+        assert_eq!(map.find(&[]), None);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.find(&[Key::A, Key::B]), Some(&2));
+
+        // Removing the empty prefix takes the whole tree
+        let removed = map.remove_prefix(&[]).unwrap();
+        assert!(map.is_empty());
+        assert_eq!(removed.len(), 1);
+    }
+
+}